@@ -2,51 +2,265 @@ use std::str::FromStr;
 
 use crate::{
     contract::MIN_CR_ALLOWED,
-    state::{AssetConfig, Position},
+    error::ContractError,
+    math::{decimal_change, MAX_DECIMALS},
+    state::{
+        read_asset_pause, read_global_pause, read_operator_approval, AssetConfig, Position,
+        PriceGuard,
+    },
 };
-use cosmwasm_std::{Decimal, Deps, Env, StdError, StdResult};
-use melange_protocol::asset::Asset;
+use cosmwasm_std::{CanonicalAddr, Decimal, Storage, Uint128};
+use melange_protocol::asset::{Asset, AssetInfo, AssetInfoRaw};
+use melange_protocol::mint::{
+    FeeSchedule, MarketHours, Operation, SECONDS_PER_DAY, SECONDS_PER_WEEK,
+};
+
+pub fn assert_min_collateral_ratio(min_collateral_ratio: Decimal) -> Result<(), ContractError> {
+    let min_allowed = Decimal::from_str(MIN_CR_ALLOWED)?;
+    if min_collateral_ratio < min_allowed {
+        Err(ContractError::InvalidMinCollateralRatio {
+            min_allowed,
+            actual: min_collateral_ratio,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+pub fn assert_decimals(decimals: u8) -> Result<u8, ContractError> {
+    if decimals > MAX_DECIMALS {
+        Err(ContractError::InvalidDecimals {
+            max_decimals: MAX_DECIMALS,
+            decimals,
+        })
+    } else {
+        Ok(decimals)
+    }
+}
+
+pub fn assert_protocol_fee(protocol_fee_rate: Decimal) -> Result<Decimal, ContractError> {
+    if protocol_fee_rate >= Decimal::one() {
+        Err(ContractError::InvalidFeeRate {
+            fee_rate: protocol_fee_rate,
+        })
+    } else {
+        Ok(protocol_fee_rate)
+    }
+}
+
+pub fn assert_fee_schedule(fee_schedule: FeeSchedule) -> Result<FeeSchedule, ContractError> {
+    assert_protocol_fee(fee_schedule.open_fee_rate)?;
+    assert_protocol_fee(fee_schedule.mint_fee_rate)?;
+    assert_protocol_fee(fee_schedule.burn_fee_rate)?;
+    assert_protocol_fee(fee_schedule.liquidation_fee_rate)?;
+    assert_protocol_fee(fee_schedule.flash_mint_fee_rate)?;
+    if fee_schedule.self_liquidation_fee_rate > fee_schedule.liquidation_fee_rate {
+        return Err(ContractError::InvalidFeeRate {
+            fee_rate: fee_schedule.self_liquidation_fee_rate,
+        });
+    }
 
-pub fn assert_revoked_collateral(
-    load_collateral_res: (Decimal, Decimal, bool),
-) -> StdResult<(Decimal, Decimal)> {
+    Ok(fee_schedule)
+}
+
+pub fn assert_auction_discount(auction_discount: Decimal) -> Result<(), ContractError> {
+    if auction_discount > Decimal::one() {
+        Err(ContractError::InvalidAuctionDiscount { auction_discount })
+    } else {
+        Ok(())
+    }
+}
+
+pub fn assert_stability_fee_rate(stability_fee_rate: Decimal) -> Result<Decimal, ContractError> {
+    if stability_fee_rate >= Decimal::one() {
+        Err(ContractError::InvalidFeeRate {
+            fee_rate: stability_fee_rate,
+        })
+    } else {
+        Ok(stability_fee_rate)
+    }
+}
+
+pub fn assert_revoked_collateral<T>(
+    load_collateral_res: (T, Decimal, bool),
+) -> Result<(T, Decimal), ContractError> {
     if load_collateral_res.2 {
-        return Err(StdError::generic_err(
-            "The collateral asset provided is no longer valid",
-        ));
+        return Err(ContractError::RevokedCollateral {});
     }
 
     Ok((load_collateral_res.0, load_collateral_res.1))
 }
 
-pub fn assert_migrated_asset(asset_config: &AssetConfig) -> StdResult<()> {
+pub fn assert_migrated_asset(asset_config: &AssetConfig) -> Result<(), ContractError> {
     if asset_config.end_price.is_some() {
-        return Err(StdError::generic_err(
-            "Operation is not allowed for the deprecated asset",
-        ));
+        return Err(ContractError::MigratedAsset {});
     }
 
     Ok(())
 }
 
-// Check zero balance & same collateral with position
-pub fn assert_collateral(deps: Deps, position: &Position, collateral: &Asset) -> StdResult<()> {
-    if !collateral
-        .info
-        .equal(&position.collateral.info.to_normal(deps.api)?)
-        || collateral.amount.is_zero()
+/// Migrated assets are priced at their end price and trade at any time
+pub fn assert_market_open(asset_config: &AssetConfig, time: u64) -> Result<(), ContractError> {
+    if asset_config.end_price.is_some() {
+        return Ok(());
+    }
+
+    match &asset_config.market_hours {
+        Some(market_hours) if !market_hours.is_open(time) => Err(ContractError::MarketClosed {}),
+        _ => Ok(()),
+    }
+}
+
+pub fn assert_market_hours(market_hours: &MarketHours) -> Result<(), ContractError> {
+    if market_hours
+        .windows
+        .iter()
+        .any(|window| window.open >= SECONDS_PER_WEEK || window.close >= SECONDS_PER_WEEK)
+        || market_hours
+            .holidays
+            .iter()
+            .any(|holiday| holiday % SECONDS_PER_DAY != 0)
     {
-        return Err(StdError::generic_err("Wrong collateral"));
+        return Err(ContractError::InvalidMarketHours {});
+    }
+
+    Ok(())
+}
+
+pub fn assert_debt_ceiling(
+    asset_config: &AssetConfig,
+    total_minted: Uint128,
+) -> Result<(), ContractError> {
+    if let Some(debt_ceiling) = asset_config.debt_ceiling {
+        if total_minted > debt_ceiling {
+            return Err(ContractError::DebtCeilingExceeded {
+                total_minted,
+                debt_ceiling,
+            });
+        }
     }
 
     Ok(())
 }
 
-// Check zero balance & same asset with position
-pub fn assert_asset(deps: Deps, position: &Position, asset: &Asset) -> StdResult<()> {
-    if !asset.info.equal(&position.asset.info.to_normal(deps.api)?) || asset.amount.is_zero() {
-        return Err(StdError::generic_err("Wrong asset"));
+pub fn assert_max_deposit(
+    max_deposit: Option<Uint128>,
+    total_deposited: Uint128,
+) -> Result<(), ContractError> {
+    if let Some(max_deposit) = max_deposit {
+        if total_deposited > max_deposit {
+            return Err(ContractError::DepositCapExceeded {
+                total_deposited,
+                max_deposit,
+            });
+        }
     }
 
     Ok(())
 }
+
+/// The sender has to be the position owner or an operator the owner approved
+/// for the operation on this position or on all of their positions
+pub fn assert_position_sender(
+    storage: &dyn Storage,
+    position: &Position,
+    sender: &CanonicalAddr,
+    operation: Operation,
+    time: u64,
+) -> Result<(), ContractError> {
+    if *sender == position.owner {
+        return Ok(());
+    }
+
+    for position_idx in [Some(position.idx), None] {
+        if let Some(approval) =
+            read_operator_approval(storage, &position.owner, sender, position_idx)?
+        {
+            if approval.allows(operation, time) {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(ContractError::Unauthorized {})
+}
+
+/// The guard must not be tripped and the price must not have moved more than
+/// the threshold from the recorded price, however old that is; a move found
+/// here only halts the operation, `CheckPriceMove` is what trips the guard
+pub fn assert_price_guard(
+    price_guard: &PriceGuard,
+    asset_info: &AssetInfo,
+    price: Decimal,
+) -> Result<(), ContractError> {
+    if price_guard.tripped {
+        return Err(ContractError::CircuitBreakerTripped {
+            asset: asset_info.to_string(),
+        });
+    }
+
+    if let Some(last_price) = price_guard.last_price {
+        if !last_price.is_zero() {
+            let price_change: Decimal = decimal_change(last_price, price);
+            if price_change > price_guard.max_price_change {
+                return Err(ContractError::PriceMoveExceeded {
+                    asset: asset_info.to_string(),
+                    max_price_change: price_guard.max_price_change,
+                    actual: price_change,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Operations on positions without debt only fall under the global pause
+pub fn assert_not_globally_paused(
+    storage: &dyn Storage,
+    operation: Operation,
+) -> Result<(), ContractError> {
+    if read_global_pause(storage)?.is_paused(operation) {
+        return Err(ContractError::Paused {
+            operation: operation.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+pub fn assert_not_paused(
+    storage: &dyn Storage,
+    operation: Operation,
+    asset_info: &AssetInfoRaw,
+) -> Result<(), ContractError> {
+    assert_not_globally_paused(storage, operation)?;
+    if read_asset_pause(storage, asset_info)?.is_paused(operation) {
+        return Err(ContractError::Paused {
+            operation: operation.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// Check zero balance; any listed collateral can join the position's basket
+pub fn assert_collateral(collateral: &Asset) -> Result<(), ContractError> {
+    if collateral.amount.is_zero() {
+        return Err(ContractError::WrongCollateral {});
+    }
+
+    Ok(())
+}
+
+// Check zero balance & the position has minted the asset; returns the debt of the asset
+pub fn assert_asset(
+    position: &Position,
+    asset_info: &AssetInfoRaw,
+    amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    match position.find_debt(asset_info) {
+        Some(debt) if !amount.is_zero() => Ok(debt.amount),
+        _ => Err(ContractError::WrongAsset {}),
+    }
+}
@@ -1,40 +1,62 @@
 use crate::{
-    asserts::{assert_min_collateral_ratio, assert_protocol_fee},
-    migration::migrate_asset_configs,
+    asserts::{
+        assert_auction_discount, assert_decimals, assert_fee_schedule, assert_market_hours,
+        assert_min_collateral_ratio, assert_protocol_fee, assert_stability_fee_rate,
+    },
+    error::ContractError,
+    flash_mint::{flash_mint, flash_mint_reply},
+    math::decimal_conversion_rate,
+    migration::{migrate_asset_configs, migrate_config, migrate_positions},
+    price_guard::{
+        acknowledge_price_move, check_price_move, query_price_guard, update_price_guard,
+    },
     positions::{
-        auction, burn, deposit, mint, open_position, query_next_position_idx, query_position,
-        query_positions, withdraw,
+        accrue_fee_index, approve_operator, auction, burn, close_position, deleverage,
+        deleverage_reply, deposit, leverage, leverage_reply, mint, open_position,
+        query_next_position_idx, query_operator_approvals, query_position, query_positions,
+        revoke_operator, self_liquidate, swap_collateral, transfer_position, withdraw,
+    },
+    querier::{
+        load_asset_price, load_collateral_asset_info, load_collateral_info,
+        query_collateral_info, PRICE_EXPIRE_TIME,
     },
     state::{
-        read_asset_config, read_config, store_asset_config, store_config, store_position_idx,
-        AssetConfig, Config,
+        read_all_asset_stats, read_all_collateral_stats, read_asset_config, read_asset_stats,
+        next_change_id, read_asset_pause, read_collateral_stats, read_config, read_global_pause,
+        read_pending_owner, read_position_idxs_with_collateral_indexer, read_queued_change,
+        read_queued_changes, read_revoked_collateral, read_revoked_collaterals,
+        remove_pending_owner, remove_queued_change, store_asset_config, store_asset_pause,
+        store_config, store_global_pause, store_pending_owner, store_position_idx,
+        store_queued_change, store_revoked_collateral, AssetConfig, AssetStats, CollateralStats,
+        Config, QueuedChange, RevokedCollateral,
     },
 };
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, from_binary, to_binary, Addr, Binary, CosmosMsg, Decimal, Deps, DepsMut,
-    Env, MessageInfo, Response, StdError, StdResult, Uint128, WasmMsg,
+    attr, from_json, to_json_binary, Addr, Binary, Decimal, Deps, DepsMut, Env, MessageInfo,
+    Reply, Response, StdError, StdResult, Uint128,
 };
 use cw20::Cw20ReceiveMsg;
 use melange_protocol::mint::{
-    AssetConfigResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
-    QueryMsg,
+    AssetConfigResponse, AssetStatsResponse, CollateralStatsResponse, ConfigResponse,
+    Cw20HookMsg, DebtCeilingResponse, DepositCapResponse, ExecuteMsg, FeeSchedule,
+    FeeScheduleResponse, InstantiateMsg, MarketHours, Operation, PauseInfo, PauseStatusResponse,
+    PendingOwnerResponse, PositionResponse, ProtocolStatsResponse, QueryMsg,
+    QueuedChangeResponse, QueuedChangesResponse, RevokedCollateralPositionsResponse,
+    RevokedCollateralResponse, RevokedCollateralsResponse, TimelockedChange,
 };
 use melange_protocol::{
-    collateral_oracle::{ExecuteMsg as CollateralOracleExecuteMsg, SourceType},
+    asset::{Asset, AssetInfo, AssetInfoRaw},
     mint::MigrateMsg,
 };
 
-use sei_cosmwasm::{
-    BulkOrderPlacementsResponse, ContractOrderResult, DepositInfo, DexTwapsResponse, EpochResponse,
-    ExchangeRatesResponse, GetLatestPriceResponse, GetOrderByIdResponse, GetOrdersResponse,
-    LiquidationRequest, LiquidationResponse, MsgPlaceOrdersResponse, OracleTwapsResponse, Order,
-    OrderSimulationResponse, OrderType, PositionDirection, SeiMsg, SeiQuerier, SeiQueryWrapper,
-    SettlementEntry, SudoMsg,
-};
+use sei_cosmwasm::{SeiMsg, SeiQueryWrapper};
 
 pub const MIN_CR_ALLOWED: &str = "1.2";
+pub const LEVERAGE_REPLY_ID: u64 = 1;
+pub const DELEVERAGE_REPLY_ID: u64 = 2;
+pub const FLASH_MINT_REPLY_ID: u64 = 3;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -42,7 +64,7 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response<SeiMsg>, ContractError> {
     let config = Config {
         owner: deps.api.addr_canonicalize(&msg.owner)?,
         oracle: deps.api.addr_canonicalize(&msg.oracle)?,
@@ -52,9 +74,18 @@ pub fn instantiate(
         melange_factory: deps.api.addr_canonicalize(&msg.melange_factory)?,
         lock: deps.api.addr_canonicalize(&msg.lock)?,
         base_denom: msg.base_denom,
+        base_decimals: assert_decimals(msg.base_decimals)?,
         token_code_id: msg.token_code_id,
         protocol_fee_rate: assert_protocol_fee(msg.protocol_fee_rate)?,
+        guardian: msg
+            .guardian
+            .map(|guardian| deps.api.addr_canonicalize(&guardian))
+            .transpose()?,
+        timelock_period: msg.timelock_period,
+        revoked_collateral_grace_period: msg.revoked_collateral_grace_period,
+        revoked_collateral_ratio: msg.revoked_collateral_ratio,
     };
+    assert_min_collateral_ratio(config.revoked_collateral_ratio)?;
 
     store_config(deps.storage, &config)?;
     store_position_idx(deps.storage, Uint128::from(1u128))?;
@@ -62,62 +93,138 @@ pub fn instantiate(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> Result<Response<SeiMsg>, ContractError> {
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::UpdateConfig {
-            owner,
-            oracle,
             collector,
-            collateral_oracle,
             melange_factory,
             lock,
             token_code_id,
-            protocol_fee_rate,
             staking,
+            guardian,
+            remove_guardian,
+            revoked_collateral_grace_period,
+            revoked_collateral_ratio,
         } => update_config(
             deps,
             info,
-            owner,
-            oracle,
             collector,
-            collateral_oracle,
             melange_factory,
             lock,
             token_code_id,
-            protocol_fee_rate,
             staking,
+            guardian,
+            remove_guardian,
+            revoked_collateral_grace_period,
+            revoked_collateral_ratio,
         ),
+        ExecuteMsg::ProposeNewOwner { owner } => propose_new_owner(deps, info, owner),
+        ExecuteMsg::ClaimOwnership {} => claim_ownership(deps, info),
+        ExecuteMsg::CancelOwnershipProposal {} => cancel_ownership_proposal(deps, info),
+        ExecuteMsg::QueueChange { change } => queue_change(deps, env, info, change),
+        ExecuteMsg::ExecuteChange { id } => execute_change(deps, env, info, id),
+        ExecuteMsg::CancelChange { id } => cancel_change(deps, info, id),
         ExecuteMsg::UpdateAsset {
             asset_token,
+            auction_discount,
             min_collateral_ratio,
+            stability_fee_rate,
+            fee_schedule,
+            debt_ceiling,
+            remove_debt_ceiling,
+            price_expire_time,
+            remove_price_expire_time,
+            market_hours,
         } => {
-            let asset_addr = deps.api.addr_validate(asset_token.as_str())?;
+            let asset_info = AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
             update_asset(
                 deps,
+                env,
                 info,
-                asset_addr,
+                asset_info,
+                auction_discount,
                 min_collateral_ratio,
+                stability_fee_rate,
+                fee_schedule,
+                debt_ceiling,
+                remove_debt_ceiling,
+                price_expire_time,
+                remove_price_expire_time,
+                market_hours,
             )
         }
         ExecuteMsg::RegisterAsset {
             asset_token,
+            auction_discount,
             min_collateral_ratio,
+            stability_fee_rate,
+            fee_schedule,
+            debt_ceiling,
         } => {
-            let asset_addr = deps.api.addr_validate(asset_token.as_str())?;
+            let asset_info = AssetInfo::Token {
+                contract_addr: deps.api.addr_validate(asset_token.as_str())?.to_string(),
+            };
+            let decimals: u8 = asset_info.query_decimals(&deps.querier, deps.api, None)?;
+            let asset_info_raw: AssetInfoRaw = asset_info.to_raw(deps.api)?;
             register_asset(
                 deps,
                 info,
-                asset_addr,
+                asset_info_raw,
+                decimals,
+                auction_discount,
                 min_collateral_ratio,
+                stability_fee_rate,
+                fee_schedule,
+                debt_ceiling,
             )
         }
+        ExecuteMsg::RegisterNativeAsset {
+            subdenom,
+            decimals,
+            auction_discount,
+            min_collateral_ratio,
+            stability_fee_rate,
+            fee_schedule,
+            debt_ceiling,
+        } => register_native_asset(
+            deps,
+            env,
+            info,
+            subdenom,
+            decimals,
+            auction_discount,
+            min_collateral_ratio,
+            stability_fee_rate,
+            fee_schedule,
+            debt_ceiling,
+        ),
         ExecuteMsg::RegisterMigration {
             asset_token,
             end_price,
         } => {
-            let asset_addr = deps.api.addr_validate(asset_token.as_str())?;
-            register_migration(deps, info, asset_addr, end_price)
+            let asset_info = AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+            register_migration(deps, info, asset_info, end_price)
+        }
+        ExecuteMsg::RegisterRevokedCollateral { collateral } => {
+            register_revoked_collateral(deps, env, info, collateral)
+        }
+        ExecuteMsg::Pause {
+            asset_token,
+            operations,
+        } => pause(deps, info, asset_token, operations),
+        ExecuteMsg::Unpause {
+            asset_token,
+            operations,
+        } => unpause(deps, info, asset_token, operations),
+        ExecuteMsg::UpdatePriceGuard {
+            asset_token,
+            max_price_change,
+            window,
+        } => update_price_guard(deps, env, info, asset_token, max_price_change, window),
+        ExecuteMsg::CheckPriceMove { asset_token } => check_price_move(deps, env, asset_token),
+        ExecuteMsg::AcknowledgePriceMove { asset_token } => {
+            acknowledge_price_move(deps, env, info, asset_token)
         }
         ExecuteMsg::OpenPosition {
             collateral,
@@ -141,22 +248,210 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
         } => {
             // only native token can be deposited directly
             if !collateral.is_native_token() {
-                return Err(StdError::generic_err("unauthorized"));
+                return Err(ContractError::Unauthorized {});
             }
 
             // Check the actual deposit happens
             collateral.assert_sent_native_token_balance(&info)?;
 
-            deposit(deps, info.sender, position_idx, collateral)
+            deposit(deps, env, info.sender, position_idx, collateral)
         }
         ExecuteMsg::Withdraw {
             position_idx,
             collateral,
-        } => withdraw(deps, info.sender, position_idx, collateral),
+        } => withdraw(deps, env, info.sender, position_idx, collateral),
+        ExecuteMsg::SwapCollateral {
+            position_idx,
+            new_collateral,
+            old_collateral,
+        } => {
+            // only native token can be deposited directly
+            if !new_collateral.is_native_token() {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            // Check the actual deposit happens
+            new_collateral.assert_sent_native_token_balance(&info)?;
+
+            swap_collateral(
+                deps,
+                env,
+                info.sender,
+                position_idx,
+                new_collateral,
+                old_collateral,
+            )
+        }
         ExecuteMsg::Mint {
             position_idx,
             asset,
         } => mint(deps, env, info.sender, position_idx, asset),
+        ExecuteMsg::Burn {
+            position_idx,
+            asset,
+        } => {
+            // only native token factory assets can be burned directly
+            if !asset.is_native_token() {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            // Check the actual deposit happens
+            asset.assert_sent_native_token_balance(&info)?;
+
+            burn(deps, env, info.sender, position_idx, asset)
+        }
+        ExecuteMsg::Auction {
+            position_idx,
+            asset,
+        } => {
+            if !asset.is_native_token() {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            // Check the actual deposit happens
+            asset.assert_sent_native_token_balance(&info)?;
+
+            auction(deps, env, info.sender, position_idx, asset)
+        }
+        ExecuteMsg::Leverage {
+            position_idx,
+            mint_amount,
+            pair,
+            min_out,
+        } => leverage(deps, env, info.sender, position_idx, mint_amount, pair, min_out),
+        ExecuteMsg::Deleverage {
+            position_idx,
+            collateral_amount,
+            pair,
+            min_out,
+        } => deleverage(
+            deps,
+            env,
+            info.sender,
+            position_idx,
+            collateral_amount,
+            pair,
+            min_out,
+        ),
+        ExecuteMsg::SelfLiquidate {
+            position_idx,
+            collateral_amount,
+            pair,
+            min_out,
+        } => self_liquidate(
+            deps,
+            env,
+            info.sender,
+            position_idx,
+            collateral_amount,
+            pair,
+            min_out,
+        ),
+        ExecuteMsg::FlashMint {
+            asset_token,
+            amount,
+            callback,
+        } => flash_mint(deps, env, info.sender, asset_token, amount, callback),
+        ExecuteMsg::ClosePosition { position_idx } => {
+            let provided: Vec<Asset> = info
+                .funds
+                .iter()
+                .map(|coin| Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: coin.denom.clone(),
+                    },
+                    amount: coin.amount,
+                })
+                .collect();
+
+            close_position(deps, env, info.sender, position_idx, provided)
+        }
+        ExecuteMsg::TransferPosition {
+            position_idx,
+            recipient,
+        } => transfer_position(deps, info.sender, position_idx, recipient),
+        ExecuteMsg::ApproveOperator {
+            operator,
+            position_idx,
+            operations,
+            expires,
+        } => approve_operator(deps, info.sender, operator, position_idx, operations, expires),
+        ExecuteMsg::RevokeOperator {
+            operator,
+            position_idx,
+        } => revoke_operator(deps, info.sender, operator, position_idx),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response<SeiMsg>, ContractError> {
+    match msg.id {
+        LEVERAGE_REPLY_ID => leverage_reply(deps, env),
+        DELEVERAGE_REPLY_ID => deleverage_reply(deps, env),
+        FLASH_MINT_REPLY_ID => flash_mint_reply(deps, env),
+        id => Err(ContractError::InvalidReplyId { id }),
+    }
+}
+
+pub fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let passed_asset: Asset = Asset {
+        info: AssetInfo::Token {
+            contract_addr: info.sender.to_string(),
+        },
+        amount: cw20_msg.amount,
+    };
+
+    match from_json(&cw20_msg.msg) {
+        Ok(Cw20HookMsg::OpenPosition {
+            asset_info,
+            collateral_ratio,
+        }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            open_position(
+                deps,
+                env,
+                cw20_sender,
+                passed_asset,
+                asset_info,
+                collateral_ratio,
+            )
+        }
+        Ok(Cw20HookMsg::Deposit { position_idx }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            deposit(deps, env, cw20_sender, position_idx, passed_asset)
+        }
+        Ok(Cw20HookMsg::SwapCollateral {
+            position_idx,
+            old_collateral,
+        }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            swap_collateral(
+                deps,
+                env,
+                cw20_sender,
+                position_idx,
+                passed_asset,
+                old_collateral,
+            )
+        }
+        Ok(Cw20HookMsg::Burn { position_idx }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            burn(deps, env, cw20_sender, position_idx, passed_asset)
+        }
+        Ok(Cw20HookMsg::Auction { position_idx }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            auction(deps, env, cw20_sender, position_idx, passed_asset)
+        }
+        Ok(Cw20HookMsg::ClosePosition { position_idx }) => {
+            let cw20_sender = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            close_position(deps, env, cw20_sender, position_idx, vec![passed_asset])
+        }
+        Err(_) => Err(ContractError::InvalidHookMessage {}),
     }
 }
 
@@ -164,38 +459,26 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
 pub fn update_config(
     deps: DepsMut,
     info: MessageInfo,
-    owner: Option<String>,
-    oracle: Option<String>,
     collector: Option<String>,
-    collector_oracle: Option<String>,
     melange_factory: Option<String>,
     lock: Option<String>,
     token_code_id: Option<u64>,
-    protocol_fee_rate: Option<Decimal>,
     staking: Option<String>,
-) -> StdResult<Response> {
+    guardian: Option<String>,
+    remove_guardian: Option<bool>,
+    revoked_collateral_grace_period: Option<u64>,
+    revoked_collateral_ratio: Option<Decimal>,
+) -> Result<Response<SeiMsg>, ContractError> {
     let mut config: Config = read_config(deps.storage)?;
 
     if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
-        return Err(StdError::generic_err("unauthorized"));
-    }
-
-    if let Some(owner) = owner {
-        config.owner = deps.api.addr_canonicalize(&owner)?;
-    }
-
-    if let Some(oracle) = oracle {
-        config.oracle = deps.api.addr_canonicalize(&oracle)?;
+        return Err(ContractError::Unauthorized {});
     }
 
     if let Some(collector) = collector {
         config.collector = deps.api.addr_canonicalize(&collector)?;
     }
 
-    if let Some(collateral_oracle) = collector_oracle {
-        config.collateral_oracle = deps.api.addr_canonicalize(&collateral_oracle)?;
-    }
-
     if let Some(melange_factory) = melange_factory {
         config.melange_factory = deps.api.addr_canonicalize(&melange_factory)?;
     }
@@ -208,58 +491,883 @@ pub fn update_config(
         config.token_code_id = token_code_id;
     }
 
-    if let Some(protocol_fee_rate) = protocol_fee_rate {
-        assert_protocol_fee(protocol_fee_rate)?;
-        config.protocol_fee_rate = protocol_fee_rate;
-    }
-
     if let Some(staking) = staking {
         config.staking = deps.api.addr_canonicalize(&staking)?;
     }
 
+    if let Some(guardian) = guardian {
+        config.guardian = Some(deps.api.addr_canonicalize(&guardian)?);
+    }
+
+    if remove_guardian.unwrap_or(false) {
+        config.guardian = None;
+    }
+
+    if let Some(revoked_collateral_grace_period) = revoked_collateral_grace_period {
+        config.revoked_collateral_grace_period = revoked_collateral_grace_period;
+    }
+
+    if let Some(revoked_collateral_ratio) = revoked_collateral_ratio {
+        assert_min_collateral_ratio(revoked_collateral_ratio)?;
+        config.revoked_collateral_ratio = revoked_collateral_ratio;
+    }
+
     store_config(deps.storage, &config)?;
     Ok(Response::new().add_attribute("action", "update_config"))
 }
 
+/// Freeze the asset at `end_price`; positions can only be closed from now on
+pub fn register_migration(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfoRaw,
+    end_price: Decimal,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info)?;
+    store_asset_config(
+        deps.storage,
+        &asset_info,
+        &AssetConfig {
+            end_price: Some(end_price),
+            min_collateral_ratio: Decimal::one(),
+            ..asset_config
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate_asset"),
+        attr("asset_token", asset_info.to_normal(deps.api)?.to_string()),
+        attr("end_price", end_price.to_string()),
+    ]))
+}
+
+/// Record when the collateral was revoked so positions on it get the grace period
+/// before they can be auctioned at `revoked_collateral_ratio`
+pub fn register_revoked_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    collateral: AssetInfo,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.owner && sender_raw != config.collateral_oracle {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let (_, is_revoked) = query_collateral_info(
+        &deps.querier,
+        deps.api.addr_humanize(&config.collateral_oracle)?,
+        collateral.to_string(),
+    )?;
+    if !is_revoked {
+        return Err(ContractError::CollateralNotRevoked {});
+    }
+
+    // the grace period runs from the first registration or observation by a position
+    let collateral_info_raw: AssetInfoRaw = collateral.to_raw(deps.api)?;
+    let revoked_collateral: RevokedCollateral =
+        match read_revoked_collateral(deps.storage, &collateral_info_raw)? {
+            Some(revoked_collateral) => revoked_collateral,
+            None => {
+                let revoked_collateral = RevokedCollateral {
+                    info: collateral_info_raw,
+                    revoked_at: env.block.time.seconds(),
+                };
+                store_revoked_collateral(deps.storage, &revoked_collateral)?;
+                revoked_collateral
+            }
+        };
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "register_revoked_collateral"),
+        attr("collateral", collateral.to_string()),
+        attr("revoked_at", revoked_collateral.revoked_at.to_string()),
+    ]))
+}
+
+pub fn propose_new_owner(deps: DepsMut, info: MessageInfo, owner: String) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    store_pending_owner(deps.storage, &deps.api.addr_canonicalize(&owner)?)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_new_owner"),
+        attr("pending_owner", owner),
+    ]))
+}
+
+pub fn claim_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response<SeiMsg>, ContractError> {
+    let mut config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if read_pending_owner(deps.storage)? != Some(sender_raw.clone()) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.owner = sender_raw;
+    store_config(deps.storage, &config)?;
+    remove_pending_owner(deps.storage);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "claim_ownership"),
+        attr("owner", info.sender),
+    ]))
+}
+
+pub fn cancel_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    remove_pending_owner(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "cancel_ownership_proposal"))
+}
+
+pub fn queue_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    change: TimelockedChange,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // validate the change up front so it can not fail once the timelock has passed
+    match &change {
+        TimelockedChange::Oracle { oracle } => {
+            deps.api.addr_validate(oracle)?;
+        }
+        TimelockedChange::CollateralOracle { collateral_oracle } => {
+            deps.api.addr_validate(collateral_oracle)?;
+        }
+        TimelockedChange::ProtocolFeeRate { protocol_fee_rate } => {
+            assert_protocol_fee(*protocol_fee_rate)?;
+        }
+        TimelockedChange::MinCollateralRatio {
+            asset_token,
+            min_collateral_ratio,
+        } => {
+            read_asset_config(
+                deps.storage,
+                &AssetInfo::from_string(deps.api, asset_token).to_raw(deps.api)?,
+            )?;
+            assert_min_collateral_ratio(*min_collateral_ratio)?;
+        }
+        TimelockedChange::TimelockPeriod { .. } => {}
+    }
+
+    let id: u64 = next_change_id(deps.storage)?;
+    let executable_at: u64 = env.block.time.seconds() + config.timelock_period;
+    store_queued_change(
+        deps.storage,
+        &QueuedChange {
+            id,
+            change,
+            executable_at,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "queue_change"),
+        attr("id", id.to_string()),
+        attr("executable_at", executable_at.to_string()),
+    ]))
+}
+
+pub fn execute_change(deps: DepsMut, env: Env, info: MessageInfo, id: u64) -> Result<Response<SeiMsg>, ContractError> {
+    let mut config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let queued_change: QueuedChange = read_queued_change(deps.storage, id)?;
+    if env.block.time.seconds() < queued_change.executable_at {
+        return Err(ContractError::Timelocked {
+            executable_at: queued_change.executable_at,
+        });
+    }
+
+    match queued_change.change {
+        TimelockedChange::Oracle { oracle } => {
+            config.oracle = deps.api.addr_canonicalize(&oracle)?;
+        }
+        TimelockedChange::CollateralOracle { collateral_oracle } => {
+            config.collateral_oracle = deps.api.addr_canonicalize(&collateral_oracle)?;
+        }
+        TimelockedChange::ProtocolFeeRate { protocol_fee_rate } => {
+            config.protocol_fee_rate = assert_protocol_fee(protocol_fee_rate)?;
+        }
+        TimelockedChange::MinCollateralRatio {
+            asset_token,
+            min_collateral_ratio,
+        } => {
+            let asset_info = AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+            let mut asset: AssetConfig = read_asset_config(deps.storage, &asset_info)?;
+            assert_min_collateral_ratio(min_collateral_ratio)?;
+            asset.min_collateral_ratio = min_collateral_ratio;
+            store_asset_config(deps.storage, &asset_info, &asset)?;
+        }
+        TimelockedChange::TimelockPeriod { timelock_period } => {
+            config.timelock_period = timelock_period;
+        }
+    }
+
+    store_config(deps.storage, &config)?;
+    remove_queued_change(deps.storage, id);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "execute_change"),
+        attr("id", id.to_string()),
+    ]))
+}
+
+pub fn cancel_change(deps: DepsMut, info: MessageInfo, id: u64) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    read_queued_change(deps.storage, id)?;
+    remove_queued_change(deps.storage, id);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_change"),
+        attr("id", id.to_string()),
+    ]))
+}
+
+pub fn pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_token: Option<String>,
+    operations: Option<Vec<Operation>>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.owner && Some(sender_raw) != config.guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_info_raw: Option<AssetInfoRaw> = match &asset_token {
+        Some(asset_token) => Some(AssetInfo::from_string(deps.api, asset_token).to_raw(deps.api)?),
+        None => None,
+    };
+
+    let mut pause_info: PauseInfo = match &asset_info_raw {
+        Some(asset_info_raw) => read_asset_pause(deps.storage, asset_info_raw)?,
+        None => read_global_pause(deps.storage)?,
+    };
+
+    match operations {
+        Some(operations) => {
+            for operation in operations {
+                if !pause_info.operations.contains(&operation) {
+                    pause_info.operations.push(operation);
+                }
+            }
+        }
+        None => pause_info.all = true,
+    }
+
+    match &asset_info_raw {
+        Some(asset_info_raw) => store_asset_pause(deps.storage, asset_info_raw, &pause_info)?,
+        None => store_global_pause(deps.storage, &pause_info)?,
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "pause"),
+        attr(
+            "asset_token",
+            asset_token.unwrap_or_else(|| "all".to_string()),
+        ),
+    ]))
+}
+
+/// Only the owner can lift a pause; unpausing single operations
+/// does not lift a pause of every operation
+pub fn unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_token: Option<String>,
+    operations: Option<Vec<Operation>>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_info_raw: Option<AssetInfoRaw> = match &asset_token {
+        Some(asset_token) => Some(AssetInfo::from_string(deps.api, asset_token).to_raw(deps.api)?),
+        None => None,
+    };
+
+    let mut pause_info: PauseInfo = match &asset_info_raw {
+        Some(asset_info_raw) => read_asset_pause(deps.storage, asset_info_raw)?,
+        None => read_global_pause(deps.storage)?,
+    };
+
+    match operations {
+        Some(operations) => pause_info
+            .operations
+            .retain(|operation| !operations.contains(operation)),
+        None => pause_info = PauseInfo::default(),
+    }
+
+    match &asset_info_raw {
+        Some(asset_info_raw) => store_asset_pause(deps.storage, asset_info_raw, &pause_info)?,
+        None => store_global_pause(deps.storage, &pause_info)?,
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "unpause"),
+        attr(
+            "asset_token",
+            asset_token.unwrap_or_else(|| "all".to_string()),
+        ),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn register_asset(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset_info: AssetInfoRaw,
+    decimals: u8,
+    auction_discount: Decimal,
+    min_collateral_ratio: Decimal,
+    stability_fee_rate: Option<Decimal>,
+    fee_schedule: Option<FeeSchedule>,
+    debt_ceiling: Option<Uint128>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    assert_decimals(decimals)?;
+    assert_auction_discount(auction_discount)?;
+    assert_min_collateral_ratio(min_collateral_ratio)?;
+    let stability_fee_rate =
+        assert_stability_fee_rate(stability_fee_rate.unwrap_or_else(Decimal::zero))?;
+
+    let config: Config = read_config(deps.storage)?;
+    let fee_schedule = assert_fee_schedule(fee_schedule.unwrap_or(FeeSchedule {
+        open_fee_rate: Decimal::zero(),
+        mint_fee_rate: Decimal::zero(),
+        burn_fee_rate: config.protocol_fee_rate,
+        liquidation_fee_rate: config.protocol_fee_rate,
+        flash_mint_fee_rate: config.protocol_fee_rate,
+        self_liquidation_fee_rate: config.protocol_fee_rate,
+    }))?;
+
+    // permission check
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if read_asset_config(deps.storage, &asset_info).is_ok() {
+        return Err(ContractError::AssetAlreadyRegistered {});
+    }
+
+    store_asset_config(
+        deps.storage,
+        &asset_info,
+        &AssetConfig {
+            info: asset_info.clone(),
+            decimals,
+            auction_discount,
+            min_collateral_ratio,
+            end_price: None,
+            stability_fee_rate,
+            fee_schedule,
+            debt_ceiling,
+            price_expire_time: None,
+            market_hours: None,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "register"),
+        attr("asset_token", asset_info.to_normal(deps.api)?.to_string()),
+    ]))
+}
+
+/// Create the token factory denom of the asset and register it;
+/// the contract stays the denom admin so it is the only minter
+#[allow(clippy::too_many_arguments)]
+pub fn register_native_asset(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    subdenom: String,
+    decimals: u8,
+    auction_discount: Decimal,
+    min_collateral_ratio: Decimal,
+    stability_fee_rate: Option<Decimal>,
+    fee_schedule: Option<FeeSchedule>,
+    debt_ceiling: Option<Uint128>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let asset_info = AssetInfoRaw::NativeToken {
+        denom: format!("factory/{}/{}", env.contract.address, subdenom),
+    };
+
+    let response = register_asset(
+        deps,
+        info,
+        asset_info,
+        decimals,
+        auction_discount,
+        min_collateral_ratio,
+        stability_fee_rate,
+        fee_schedule,
+        debt_ceiling,
+    )?;
+
+    Ok(response.add_message(SeiMsg::CreateDenom { subdenom }))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_asset(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset_info: AssetInfoRaw,
+    auction_discount: Option<Decimal>,
+    min_collateral_ratio: Option<Decimal>,
+    stability_fee_rate: Option<Decimal>,
+    fee_schedule: Option<FeeSchedule>,
+    debt_ceiling: Option<Uint128>,
+    remove_debt_ceiling: Option<bool>,
+    price_expire_time: Option<u64>,
+    remove_price_expire_time: Option<bool>,
+    market_hours: Option<MarketHours>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let mut asset: AssetConfig = read_asset_config(deps.storage, &asset_info)?;
+
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if let Some(auction_discount) = auction_discount {
+        assert_auction_discount(auction_discount)?;
+        asset.auction_discount = auction_discount;
+    }
+
+    if let Some(min_collateral_ratio) = min_collateral_ratio {
+        assert_min_collateral_ratio(min_collateral_ratio)?;
+        if min_collateral_ratio < asset.min_collateral_ratio {
+            return Err(ContractError::TimelockRequired {});
+        }
+        asset.min_collateral_ratio = min_collateral_ratio;
+    }
+
+    if let Some(fee_schedule) = fee_schedule {
+        asset.fee_schedule = assert_fee_schedule(fee_schedule)?;
+    }
+
+    if let Some(debt_ceiling) = debt_ceiling {
+        asset.debt_ceiling = Some(debt_ceiling);
+    }
+
+    if remove_debt_ceiling.unwrap_or(false) {
+        asset.debt_ceiling = None;
+    }
+
+    if let Some(price_expire_time) = price_expire_time {
+        asset.price_expire_time = Some(price_expire_time);
+    }
+
+    if remove_price_expire_time.unwrap_or(false) {
+        asset.price_expire_time = None;
+    }
+
+    if let Some(market_hours) = market_hours {
+        assert_market_hours(&market_hours)?;
+        asset.market_hours = if market_hours.windows.is_empty() {
+            None
+        } else {
+            Some(market_hours)
+        };
+    }
+
+    if let Some(stability_fee_rate) = stability_fee_rate {
+        // accrue the fee index with the old rate before switching over
+        accrue_fee_index(
+            deps.storage,
+            &asset_info,
+            asset.stability_fee_rate,
+            env.block.time.seconds(),
+        )?;
+        asset.stability_fee_rate = assert_stability_fee_rate(stability_fee_rate)?;
+    }
+
+    store_asset_config(deps.storage, &asset_info, &asset)?;
+    Ok(Response::new().add_attribute("action", "update_asset"))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Config {} => to_binary(&query_config(deps)?),
-        QueryMsg::AssetConfig { asset_token } => to_binary(&query_asset_config(deps, asset_token)?),
-        QueryMsg::Position { position_idx } => to_binary(&query_position(deps, position_idx)?),
+        QueryMsg::Config {} => to_json_binary(&query_config(deps)?),
+        QueryMsg::AssetConfig { asset_token } => {
+            to_json_binary(&query_asset_config(deps, asset_token)?)
+        }
+        QueryMsg::Position { position_idx } => {
+            to_json_binary(&query_position(deps, env, position_idx)?)
+        }
         QueryMsg::Positions {
             owner_addr,
             asset_token,
             start_after,
             limit,
             order_by,
-        } => to_binary(&query_positions(
+        } => to_json_binary(&query_positions(
             deps,
+            env,
             owner_addr,
             asset_token,
             start_after,
             limit,
             order_by,
         )?),
-        QueryMsg::NextPositionIdx {} => to_binary(&query_next_position_idx(deps)?),
+        QueryMsg::NextPositionIdx {} => to_json_binary(&query_next_position_idx(deps)?),
+        QueryMsg::AssetStats { asset_token } => {
+            to_json_binary(&query_asset_stats(deps, asset_token)?)
+        }
+        QueryMsg::ProtocolStats {} => to_json_binary(&query_protocol_stats(deps)?),
+        QueryMsg::FeeSchedule { asset_token } => {
+            to_json_binary(&query_fee_schedule(deps, asset_token)?)
+        }
+        QueryMsg::DebtCeiling { asset_token } => {
+            to_json_binary(&query_debt_ceiling(deps, asset_token)?)
+        }
+        QueryMsg::DepositCap { collateral } => {
+            to_json_binary(&query_deposit_cap(deps, collateral)?)
+        }
+        QueryMsg::PauseStatus { asset_token } => {
+            to_json_binary(&query_pause_status(deps, asset_token)?)
+        }
+        QueryMsg::PendingOwner {} => to_json_binary(&query_pending_owner(deps)?),
+        QueryMsg::QueuedChanges { start_after, limit } => {
+            to_json_binary(&query_queued_changes(deps, start_after, limit)?)
+        }
+        QueryMsg::OperatorApprovals { owner } => {
+            to_json_binary(&query_operator_approvals(deps, owner)?)
+        }
+        QueryMsg::RevokedCollaterals {} => to_json_binary(&query_revoked_collaterals(deps)?),
+        QueryMsg::RevokedCollateralPositions {
+            collateral,
+            start_after,
+            limit,
+        } => to_json_binary(&query_revoked_collateral_positions(
+            deps,
+            env,
+            collateral,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::PriceGuard { asset_token } => {
+            to_json_binary(&query_price_guard(deps, asset_token)?)
+        }
     }
 }
 
+pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let state: Config = read_config(deps.storage)?;
+    let resp = ConfigResponse {
+        owner: deps.api.addr_humanize(&state.owner)?.to_string(),
+        oracle: deps.api.addr_humanize(&state.oracle)?.to_string(),
+        collector: deps.api.addr_humanize(&state.collector)?.to_string(),
+        collateral_oracle: deps.api.addr_humanize(&state.collateral_oracle)?.to_string(),
+        staking: deps.api.addr_humanize(&state.staking)?.to_string(),
+        melange_factory: deps.api.addr_humanize(&state.melange_factory)?.to_string(),
+        lock: deps.api.addr_humanize(&state.lock)?.to_string(),
+        base_denom: state.base_denom,
+        base_decimals: state.base_decimals,
+        token_code_id: state.token_code_id,
+        protocol_fee_rate: state.protocol_fee_rate,
+        guardian: state
+            .guardian
+            .map(|guardian| deps.api.addr_humanize(&guardian).map(|addr| addr.to_string()))
+            .transpose()?,
+        timelock_period: state.timelock_period,
+        revoked_collateral_grace_period: state.revoked_collateral_grace_period,
+        revoked_collateral_ratio: state.revoked_collateral_ratio,
+    };
+
+    Ok(resp)
+}
+
 pub fn query_asset_config(deps: Deps, asset_token: String) -> StdResult<AssetConfigResponse> {
     let asset_config: AssetConfig = read_asset_config(
         deps.storage,
-        &deps.api.addr_canonicalize(asset_token.as_str())?,
+        &AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?,
     )?;
 
     let resp = AssetConfigResponse {
-        token: deps
-            .api
-            .addr_humanize(&asset_config.token)
-            .unwrap()
-            .to_string(),
+        token: asset_config.info.to_normal(deps.api)?.to_string(),
+        decimals: asset_config.decimals,
+        auction_discount: asset_config.auction_discount,
         min_collateral_ratio: asset_config.min_collateral_ratio,
         end_price: asset_config.end_price,
+        stability_fee_rate: asset_config.stability_fee_rate,
+        fee_schedule: asset_config.fee_schedule,
+        debt_ceiling: asset_config.debt_ceiling,
+        price_expire_time: asset_config.price_expire_time.unwrap_or(PRICE_EXPIRE_TIME),
+        market_hours: asset_config.market_hours,
     };
 
     Ok(resp)
 }
+
+pub fn query_fee_schedule(deps: Deps, asset_token: String) -> StdResult<FeeScheduleResponse> {
+    let asset_config: AssetConfig = read_asset_config(
+        deps.storage,
+        &AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?,
+    )?;
+
+    Ok(FeeScheduleResponse {
+        asset_token,
+        fee_schedule: asset_config.fee_schedule,
+    })
+}
+
+pub fn query_debt_ceiling(deps: Deps, asset_token: String) -> StdResult<DebtCeilingResponse> {
+    let asset_info_raw = AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
+    let asset_stats: AssetStats = read_asset_stats(deps.storage, &asset_info_raw)?;
+
+    Ok(DebtCeilingResponse {
+        asset_token,
+        debt_ceiling: asset_config.debt_ceiling,
+        total_minted: asset_stats.total_minted,
+    })
+}
+
+pub fn query_deposit_cap(deps: Deps, collateral: AssetInfo) -> StdResult<DepositCapResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collateral_info_raw: AssetInfoRaw = collateral.to_raw(deps.api)?;
+    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+    let max_deposit: Option<Uint128> =
+        load_collateral_asset_info(deps, collateral_oracle, &collateral_info_raw)?.max_deposit;
+    let collateral_stats: CollateralStats =
+        read_collateral_stats(deps.storage, &collateral_info_raw)?;
+
+    Ok(DepositCapResponse {
+        collateral,
+        max_deposit,
+        total_deposited: collateral_stats.total_deposited,
+    })
+}
+
+fn revoked_collateral_response(
+    deps: Deps,
+    config: &Config,
+    revoked_collateral: RevokedCollateral,
+) -> StdResult<RevokedCollateralResponse> {
+    Ok(RevokedCollateralResponse {
+        collateral: revoked_collateral.info.to_normal(deps.api)?,
+        revoked_at: revoked_collateral.revoked_at,
+        auctionable_at: revoked_collateral.revoked_at + config.revoked_collateral_grace_period,
+    })
+}
+
+pub fn query_revoked_collaterals(deps: Deps) -> StdResult<RevokedCollateralsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collaterals: Vec<RevokedCollateralResponse> = read_revoked_collaterals(deps.storage)?
+        .into_iter()
+        .map(|revoked_collateral| revoked_collateral_response(deps, &config, revoked_collateral))
+        .collect::<StdResult<Vec<RevokedCollateralResponse>>>()?;
+
+    Ok(RevokedCollateralsResponse { collaterals })
+}
+
+pub fn query_revoked_collateral_positions(
+    deps: Deps,
+    env: Env,
+    collateral: AssetInfo,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+) -> StdResult<RevokedCollateralPositionsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collateral_info_raw: AssetInfoRaw = collateral.to_raw(deps.api)?;
+    let revoked_collateral: RevokedCollateral =
+        read_revoked_collateral(deps.storage, &collateral_info_raw)?
+            .ok_or_else(|| StdError::not_found("RevokedCollateral"))?;
+
+    let positions: Vec<PositionResponse> = read_position_idxs_with_collateral_indexer(
+        deps.storage,
+        &collateral_info_raw,
+        start_after,
+        limit,
+    )?
+    .into_iter()
+    .map(|position_idx| query_position(deps, env.clone(), position_idx))
+    .collect::<StdResult<Vec<PositionResponse>>>()?;
+
+    Ok(RevokedCollateralPositionsResponse {
+        revoked_collateral: revoked_collateral_response(deps, &config, revoked_collateral)?,
+        positions,
+    })
+}
+
+pub fn query_pending_owner(deps: Deps) -> StdResult<PendingOwnerResponse> {
+    let pending_owner: Option<String> = match read_pending_owner(deps.storage)? {
+        Some(pending_owner) => Some(deps.api.addr_humanize(&pending_owner)?.to_string()),
+        None => None,
+    };
+
+    Ok(PendingOwnerResponse { pending_owner })
+}
+
+pub fn query_queued_changes(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<QueuedChangesResponse> {
+    let changes: Vec<QueuedChangeResponse> = read_queued_changes(deps.storage, start_after, limit)?
+        .into_iter()
+        .map(|queued_change| QueuedChangeResponse {
+            id: queued_change.id,
+            change: queued_change.change,
+            executable_at: queued_change.executable_at,
+        })
+        .collect();
+
+    Ok(QueuedChangesResponse { changes })
+}
+
+pub fn query_pause_status(
+    deps: Deps,
+    asset_token: Option<String>,
+) -> StdResult<PauseStatusResponse> {
+    let asset: Option<PauseInfo> = match asset_token {
+        Some(asset_token) => Some(read_asset_pause(
+            deps.storage,
+            &AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?,
+        )?),
+        None => None,
+    };
+
+    Ok(PauseStatusResponse {
+        global: read_global_pause(deps.storage)?,
+        asset,
+    })
+}
+
+pub fn query_asset_stats(deps: Deps, asset_token: String) -> StdResult<AssetStatsResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let asset_info_raw = AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+    let asset_stats: AssetStats = read_asset_stats(deps.storage, &asset_info_raw)?;
+
+    asset_stats_response(deps, &config, asset_stats)
+}
+
+pub fn query_protocol_stats(deps: Deps) -> StdResult<ProtocolStatsResponse> {
+    let config: Config = read_config(deps.storage)?;
+
+    let assets: Vec<AssetStatsResponse> = read_all_asset_stats(deps.storage)?
+        .into_iter()
+        .map(|asset_stats| asset_stats_response(deps, &config, asset_stats))
+        .collect::<StdResult<Vec<AssetStatsResponse>>>()?;
+
+    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+    let collaterals: Vec<CollateralStatsResponse> = read_all_collateral_stats(deps.storage)?
+        .into_iter()
+        .map(|collateral_stats| {
+            // a collateral that can not be priced is valued at zero
+            let total_deposited_value: Uint128 =
+                load_collateral_asset_info(deps, collateral_oracle.clone(), &collateral_stats.info)
+                    .and_then(|collateral_info| {
+                        let (collateral_price, _, _) = load_collateral_info(
+                            deps,
+                            collateral_oracle.clone(),
+                            &collateral_stats.info,
+                            &collateral_info,
+                            false,
+                        )?;
+                        Ok(collateral_stats.total_deposited
+                            * decimal_conversion_rate(
+                                collateral_price,
+                                collateral_info.decimals,
+                                Decimal::one(),
+                                config.base_decimals,
+                            ))
+                    })
+                    .unwrap_or_default();
+
+            Ok(CollateralStatsResponse {
+                collateral: collateral_stats.info.to_normal(deps.api)?,
+                total_deposited: collateral_stats.total_deposited,
+                total_deposited_value,
+            })
+        })
+        .collect::<StdResult<Vec<CollateralStatsResponse>>>()?;
+
+    let total_debt_value: Uint128 = assets.iter().map(|a| a.total_minted_value).sum();
+    let total_collateral_value: Uint128 =
+        collaterals.iter().map(|c| c.total_deposited_value).sum();
+
+    Ok(ProtocolStatsResponse {
+        assets,
+        collaterals,
+        total_debt_value,
+        total_collateral_value,
+    })
+}
+
+fn asset_stats_response(
+    deps: Deps,
+    config: &Config,
+    asset_stats: AssetStats,
+) -> StdResult<AssetStatsResponse> {
+    // an asset that can not be priced is valued at zero
+    let oracle: Addr = deps.api.addr_humanize(&config.oracle)?;
+    let total_minted_value: Uint128 = load_asset_price(deps, oracle, &asset_stats.info, false)
+        .and_then(|asset_price| {
+            let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_stats.info)?;
+            Ok(asset_stats.total_minted
+                * decimal_conversion_rate(
+                    asset_price,
+                    asset_config.decimals,
+                    Decimal::one(),
+                    config.base_decimals,
+                ))
+        })
+        .unwrap_or_default();
+
+    Ok(AssetStatsResponse {
+        asset_token: asset_stats.info.to_normal(deps.api)?.to_string(),
+        total_minted: asset_stats.total_minted,
+        position_count: asset_stats.position_count,
+        total_minted_value,
+    })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(
+    deps: DepsMut,
+    _env: Env,
+    msg: MigrateMsg,
+) -> Result<Response<SeiMsg>, ContractError> {
+    assert_auction_discount(msg.auction_discount)?;
+    assert_decimals(msg.base_decimals)?;
+    assert_min_collateral_ratio(msg.revoked_collateral_ratio)?;
+
+    migrate_config(deps.storage, &msg)?;
+    migrate_positions(deps.storage)?;
+    migrate_asset_configs(deps, msg.auction_discount)?;
+
+    Ok(Response::default())
+}
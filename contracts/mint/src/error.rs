@@ -0,0 +1,132 @@
+use cosmwasm_std::{Decimal, OverflowError, StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Invalid cw20 hook message")]
+    InvalidHookMessage {},
+
+    #[error("Asset was already registered")]
+    AssetAlreadyRegistered {},
+
+    #[error("min_collateral_ratio must be bigger or equal than {min_allowed}; got {actual}")]
+    InvalidMinCollateralRatio { min_allowed: Decimal, actual: Decimal },
+
+    #[error("Fee rate must be smaller than 1; got {fee_rate}")]
+    InvalidFeeRate { fee_rate: Decimal },
+
+    #[error("auction_discount must be smaller than 1; got {auction_discount}")]
+    InvalidAuctionDiscount { auction_discount: Decimal },
+
+    #[error("Wrong collateral")]
+    WrongCollateral {},
+
+    #[error("Wrong asset")]
+    WrongAsset {},
+
+    #[error("The collateral asset provided is no longer valid")]
+    RevokedCollateral {},
+
+    #[error("The collateral has not been revoked in the collateral oracle")]
+    CollateralNotRevoked {},
+
+    #[error("Operation is not allowed for the deprecated asset")]
+    MigratedAsset {},
+
+    #[error("Operation is not allowed while the market of the asset is closed")]
+    MarketClosed {},
+
+    #[error("Market windows must be within a week and holidays must start at 00:00 UTC")]
+    InvalidMarketHours {},
+
+    #[error("Operation {operation} is paused")]
+    Paused { operation: String },
+
+    #[error("Collateral is too small")]
+    CollateralTooSmall {},
+
+    #[error("Collateral ratio {actual} is lower than the required {required}")]
+    LowCollateralRatio { required: Decimal, actual: Decimal },
+
+    #[error("Cannot liquidate a safely collateralized position; ratio {actual} > {required}")]
+    SafelyCollateralized { required: Decimal, actual: Decimal },
+
+    #[error("Cannot withdraw more than you provide; requested {requested}, available {available}")]
+    InsufficientCollateral {
+        requested: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Cannot burn more than the position debt; requested {requested}, available {available}")]
+    InsufficientDebt {
+        requested: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Cannot close the position without repaying its debt; required {required}, provided {provided}")]
+    InsufficientRepayment {
+        required: Uint128,
+        provided: Uint128,
+    },
+
+    #[error("Cannot close the position with its debt in {asset} unpaid; burn it first")]
+    UnpaidDebt { asset: String },
+
+    #[error("Cannot mint over the debt ceiling; total minted {total_minted} > ceiling {debt_ceiling}")]
+    DebtCeilingExceeded {
+        total_minted: Uint128,
+        debt_ceiling: Uint128,
+    },
+
+    #[error("Cannot deposit over the collateral cap; total deposited {total_deposited} > cap {max_deposit}")]
+    DepositCapExceeded {
+        total_deposited: Uint128,
+        max_deposit: Uint128,
+    },
+
+    #[error("Swap returned {received}, less than the minimum {min_out}")]
+    InsufficientSwapOutput { min_out: Uint128, received: Uint128 },
+
+    #[error("Another flash mint is in progress")]
+    FlashMintInProgress {},
+
+    #[error("Another leverage or deleverage swap is in progress")]
+    SwapInProgress {},
+
+    #[error("Flash mint was not repaid; required {required}, repaid {repaid}")]
+    FlashMintNotRepaid { required: Uint128, repaid: Uint128 },
+
+    #[error("Unknown reply id {id}")]
+    InvalidReplyId { id: u64 },
+
+    #[error("decimals must be at most {max_decimals}; got {decimals}")]
+    InvalidDecimals { max_decimals: u8, decimals: u8 },
+
+    #[error("max_price_change must be bigger than 0; got {max_price_change}")]
+    InvalidMaxPriceChange { max_price_change: Decimal },
+
+    #[error("Circuit breaker of {asset} is tripped")]
+    CircuitBreakerTripped { asset: String },
+
+    #[error("Price of {asset} moved by {actual}, more than the allowed {max_price_change}")]
+    PriceMoveExceeded {
+        asset: String,
+        max_price_change: Decimal,
+        actual: Decimal,
+    },
+
+    #[error("Decreasing min_collateral_ratio has to be queued through the timelock")]
+    TimelockRequired {},
+
+    #[error("Change is timelocked until {executable_at}")]
+    Timelocked { executable_at: u64 },
+}
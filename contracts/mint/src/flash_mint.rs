@@ -0,0 +1,129 @@
+use cosmwasm_std::{
+    attr, Addr, Binary, CosmosMsg, DepsMut, Env, Response, StdError, SubMsg, Uint128, WasmMsg,
+};
+
+use crate::{
+    asserts::{assert_debt_ceiling, assert_migrated_asset, assert_not_paused},
+    contract::FLASH_MINT_REPLY_ID,
+    error::ContractError,
+    positions::{burn_asset_msg, mint_asset_msgs, transfer_msgs},
+    state::{
+        read_asset_config, read_asset_stats, read_collateral_stats, read_config,
+        read_pending_flash_mint, remove_pending_flash_mint, store_pending_flash_mint, AssetConfig,
+        Config, PendingFlashMint,
+    },
+};
+use melange_protocol::{
+    asset::{Asset, AssetInfo, AssetInfoRaw},
+    mint::Operation,
+};
+use sei_cosmwasm::SeiMsg;
+
+/// Mint the asset to the sender and execute its callback;
+/// the repayment is verified in the reply
+pub fn flash_mint(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    asset_token: String,
+    amount: Uint128,
+    callback: Binary,
+) -> Result<Response<SeiMsg>, ContractError> {
+    // the callback must not open another flash mint over the pending one
+    if read_pending_flash_mint(deps.storage)?.is_some() {
+        return Err(ContractError::FlashMintInProgress {});
+    }
+
+    let asset_info: AssetInfo = AssetInfo::from_string(deps.api, &asset_token);
+    let asset_info_raw: AssetInfoRaw = asset_info.to_raw(deps.api)?;
+    assert_not_paused(deps.storage, Operation::FlashMint, &asset_info_raw)?;
+
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
+    assert_migrated_asset(&asset_config)?;
+    if amount.is_zero() {
+        return Err(ContractError::WrongAsset {});
+    }
+
+    // the flash minted amount counts against the debt ceiling while it is outstanding
+    let asset_stats = read_asset_stats(deps.storage, &asset_info_raw)?;
+    assert_debt_ceiling(&asset_config, asset_stats.total_minted + amount)?;
+
+    let pending_flash_mint = PendingFlashMint {
+        amount,
+        fee: amount * asset_config.fee_schedule.flash_mint_fee_rate,
+        prev_balance: asset_info.query_pool(
+            &deps.querier,
+            deps.api,
+            env.contract.address.clone(),
+        )?,
+        prev_deposited: read_collateral_stats(deps.storage, &asset_info_raw)?.total_deposited,
+        asset_info: asset_info_raw,
+    };
+    store_pending_flash_mint(deps.storage, &pending_flash_mint)?;
+
+    let asset = Asset {
+        info: asset_info,
+        amount,
+    };
+    Ok(Response::new()
+        .add_messages(mint_asset_msgs(&asset, &sender)?)
+        .add_submessage(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: sender.to_string(),
+                msg: callback,
+                funds: vec![],
+            }),
+            FLASH_MINT_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "flash_mint"),
+            attr("receiver", sender),
+            attr("amount", asset.to_string()),
+        ]))
+}
+
+/// Burn the flash minted amount once repaid;
+/// the fee and anything paid over it go to the collector
+pub fn flash_mint_reply(deps: DepsMut, env: Env) -> Result<Response<SeiMsg>, ContractError> {
+    let pending_flash_mint: PendingFlashMint = read_pending_flash_mint(deps.storage)?
+        .ok_or_else(|| StdError::not_found("PendingFlashMint"))?;
+    remove_pending_flash_mint(deps.storage);
+
+    let asset_info: AssetInfo = pending_flash_mint.asset_info.to_normal(deps.api)?;
+    let balance: Uint128 =
+        asset_info.query_pool(&deps.querier, deps.api, env.contract.address.clone())?;
+    let deposited: Uint128 =
+        read_collateral_stats(deps.storage, &pending_flash_mint.asset_info)?.total_deposited;
+
+    // collateral deposited or withdrawn during the callback is not a repayment
+    let repaid: Uint128 = (balance + pending_flash_mint.prev_deposited)
+        .saturating_sub(pending_flash_mint.prev_balance + deposited);
+    let required: Uint128 = pending_flash_mint.amount + pending_flash_mint.fee;
+    if repaid < required {
+        return Err(ContractError::FlashMintNotRepaid { required, repaid });
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let burned = Asset {
+        info: asset_info.clone(),
+        amount: pending_flash_mint.amount,
+    };
+    let fee = Asset {
+        info: asset_info,
+        amount: repaid - pending_flash_mint.amount,
+    };
+
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = vec![burn_asset_msg(&burned)?];
+    messages.extend(transfer_msgs(
+        std::slice::from_ref(&fee),
+        deps.api.addr_humanize(&config.collector)?,
+    )?);
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "flash_mint_repay"),
+            attr("burn_amount", burned.to_string()),
+            attr("flash_mint_fee", fee.to_string()),
+        ]))
+}
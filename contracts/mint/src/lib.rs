@@ -1,6 +1,13 @@
 pub mod contract;
+pub mod error;
 mod state;
+mod migration;
 mod positions;
+mod flash_mint;
+mod price_guard;
 mod asserts;
 mod querier;
 mod math;
+
+#[cfg(test)]
+mod testing;
@@ -24,6 +24,35 @@ pub fn decimal_multiplication(a: Decimal, b: Decimal) -> Decimal {
     Decimal::from_ratio(a * DECIMAL_FRACTIONAL * b, DECIMAL_FRACTIONAL)
 }
 
+/// most decimals of an asset or collateral, keeping the conversion rates in range
+pub const MAX_DECIMALS: u8 = 18;
+
+/// Rate converting the smallest unit of the `from` asset into the smallest unit
+/// of the `to` asset, given both prices per whole unit
+pub fn decimal_conversion_rate(
+    from_price: Decimal,
+    from_decimals: u8,
+    to_price: Decimal,
+    to_decimals: u8,
+) -> Decimal {
+    decimal_division(from_price, to_price)
+        * Decimal::from_ratio(
+            10u128.pow(to_decimals.into()),
+            10u128.pow(from_decimals.into()),
+        )
+}
+
+/// relative change from `prev` to `next`
+pub fn decimal_change(prev: Decimal, next: Decimal) -> Decimal {
+    let diff: Decimal = if next > prev {
+        decimal_subtraction(next, prev)
+    } else {
+        decimal_subtraction(prev, next)
+    };
+
+    decimal_division(diff, prev)
+}
+
 pub fn decimal_min(a: Decimal, b: Decimal) -> Decimal {
     if a < b {
         a
@@ -0,0 +1,207 @@
+// buckets from cosmwasm-storage until the state moves to cw-storage-plus
+#![allow(deprecated)]
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{CanonicalAddr, Decimal, DepsMut, Order, StdResult, Storage, Uint128};
+use cosmwasm_storage::{singleton_read, Bucket, ReadonlyBucket};
+
+use crate::state::{
+    create_position, read_config, store_config, AssetConfig, Config, Debt, Position, KEY_CONFIG,
+    PREFIX_ASSET_CONFIG, PREFIX_POSITION,
+};
+use melange_protocol::asset::{query_token_decimals, AssetInfoRaw, AssetRaw};
+use melange_protocol::mint::{FeeSchedule, MigrateMsg};
+
+/// Position layout with a single minted asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyPosition {
+    pub idx: Uint128,
+    pub owner: CanonicalAddr,
+    pub collateral: LegacyCollateral,
+    pub asset: AssetRaw,
+    /// not stored by positions opened before the stability fee
+    #[serde(default)]
+    pub fee_index: Decimal,
+}
+
+/// Collateral of a legacy position, stored before or after collateral baskets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum LegacyCollateral {
+    Single(AssetRaw),
+    Basket(Vec<AssetRaw>),
+}
+
+/// Asset config layout of cw20 assets before the fee and decimals settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyAssetConfig {
+    pub token: CanonicalAddr,
+    pub min_collateral_ratio: Decimal,
+    pub end_price: Option<Decimal>,
+}
+
+/// Config layout before the decimals, timelock and revoked collateral settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyConfig {
+    pub owner: CanonicalAddr,
+    pub oracle: CanonicalAddr,
+    pub collector: CanonicalAddr,
+    pub collateral_oracle: CanonicalAddr,
+    pub staking: CanonicalAddr,
+    pub melange_factory: CanonicalAddr,
+    pub lock: CanonicalAddr,
+    pub base_denom: String,
+    pub token_code_id: u64,
+    pub protocol_fee_rate: Decimal,
+}
+
+// stored entries are tried in the current layout first, so entries migrated
+// before are recognized and anything in neither layout fails the migration
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredConfig {
+    Current(Config),
+    Legacy(LegacyConfig),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredPosition {
+    Current(Position),
+    Legacy(LegacyPosition),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredAssetConfig {
+    Current(Box<AssetConfig>),
+    Legacy(LegacyAssetConfig),
+}
+
+/// Complete a legacy config with the settings added since; there is no
+/// guardian until the owner sets one.
+pub fn migrate_config(storage: &mut dyn Storage, msg: &MigrateMsg) -> StdResult<()> {
+    let legacy_config: LegacyConfig = match singleton_read(storage, KEY_CONFIG).load()? {
+        StoredConfig::Current(_) => return Ok(()),
+        StoredConfig::Legacy(legacy_config) => legacy_config,
+    };
+
+    store_config(
+        storage,
+        &Config {
+            owner: legacy_config.owner,
+            oracle: legacy_config.oracle,
+            collector: legacy_config.collector,
+            collateral_oracle: legacy_config.collateral_oracle,
+            staking: legacy_config.staking,
+            melange_factory: legacy_config.melange_factory,
+            lock: legacy_config.lock,
+            base_denom: legacy_config.base_denom,
+            base_decimals: msg.base_decimals,
+            token_code_id: legacy_config.token_code_id,
+            protocol_fee_rate: legacy_config.protocol_fee_rate,
+            guardian: None,
+            timelock_period: msg.timelock_period,
+            revoked_collateral_grace_period: msg.revoked_collateral_grace_period,
+            revoked_collateral_ratio: msg.revoked_collateral_ratio,
+        },
+    )
+}
+
+/// Move every legacy position to the collateral basket and debt list layout.
+/// The stats and the collateral index did not exist for legacy positions, so
+/// they are rebuilt while saving; positions already in the new layout are left
+/// untouched.
+pub fn migrate_positions(storage: &mut dyn Storage) -> StdResult<()> {
+    let positions_bucket: ReadonlyBucket<StoredPosition> =
+        ReadonlyBucket::new(storage, PREFIX_POSITION);
+
+    let mut legacy_positions: Vec<LegacyPosition> = vec![];
+    for item in positions_bucket.range(None, None, Order::Ascending) {
+        if let (_, StoredPosition::Legacy(legacy_position)) = item? {
+            legacy_positions.push(legacy_position);
+        }
+    }
+
+    for legacy_position in legacy_positions.into_iter() {
+        let collateral: Vec<AssetRaw> = match legacy_position.collateral {
+            LegacyCollateral::Single(collateral) => vec![collateral],
+            LegacyCollateral::Basket(collateral) => collateral,
+        };
+
+        create_position(
+            storage,
+            legacy_position.idx,
+            &Position {
+                idx: legacy_position.idx,
+                owner: legacy_position.owner,
+                collateral,
+                // a zero fee index starts accruing on the next settlement
+                debt: vec![Debt {
+                    info: legacy_position.asset.info,
+                    amount: legacy_position.asset.amount,
+                    fee_index: legacy_position.fee_index,
+                }],
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Complete the legacy asset configs; the decimals are read from the token,
+/// the fee schedule charges the protocol fee on burns and auctions like a newly
+/// registered asset, and neither a stability fee nor a debt ceiling is set.
+pub fn migrate_asset_configs(deps: DepsMut, auction_discount: Decimal) -> StdResult<()> {
+    let asset_configs_bucket: ReadonlyBucket<StoredAssetConfig> =
+        ReadonlyBucket::new(deps.storage, PREFIX_ASSET_CONFIG);
+
+    let mut legacy_asset_configs: Vec<LegacyAssetConfig> = vec![];
+    for item in asset_configs_bucket.range(None, None, Order::Ascending) {
+        if let (_, StoredAssetConfig::Legacy(legacy_asset_config)) = item? {
+            legacy_asset_configs.push(legacy_asset_config);
+        }
+    }
+    if legacy_asset_configs.is_empty() {
+        return Ok(());
+    }
+
+    let protocol_fee_rate: Decimal = read_config(deps.storage)?.protocol_fee_rate;
+    for legacy_asset_config in legacy_asset_configs.into_iter() {
+        let decimals: u8 = query_token_decimals(
+            &deps.querier,
+            deps.api.addr_humanize(&legacy_asset_config.token)?,
+        )?;
+        let asset_info = AssetInfoRaw::Token {
+            contract_addr: legacy_asset_config.token,
+        };
+
+        let mut asset_configs_bucket: Bucket<AssetConfig> =
+            Bucket::new(deps.storage, PREFIX_ASSET_CONFIG);
+        asset_configs_bucket.save(
+            asset_info.as_bytes(),
+            &AssetConfig {
+                info: asset_info.clone(),
+                decimals,
+                auction_discount,
+                min_collateral_ratio: legacy_asset_config.min_collateral_ratio,
+                end_price: legacy_asset_config.end_price,
+                stability_fee_rate: Decimal::zero(),
+                fee_schedule: FeeSchedule {
+                    burn_fee_rate: protocol_fee_rate,
+                    liquidation_fee_rate: protocol_fee_rate,
+                    flash_mint_fee_rate: protocol_fee_rate,
+                    self_liquidation_fee_rate: protocol_fee_rate,
+                    ..FeeSchedule::default()
+                },
+                debt_ceiling: None,
+                price_expire_time: None,
+                market_hours: None,
+            },
+        )?;
+    }
+
+    Ok(())
+}
@@ -1,115 +1,214 @@
 use cosmwasm_std::{
-    attr, to_binary, Addr, Attribute, CosmosMsg, Decimal, Deps, DepsMut, Env, Response, StdError,
-    StdResult, Uint128, WasmMsg,
+    attr, to_json_binary, Addr, Api, Attribute, BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, Response, StdError, StdResult, Storage, SubMsg, Uint128, WasmMsg,
 };
 
 use crate::{
+    contract::{DELEVERAGE_REPLY_ID, LEVERAGE_REPLY_ID},
     asserts::{
-        assert_asset, assert_collateral, assert_migrated_asset, assert_revoked_collateral,
+        assert_asset, assert_collateral, assert_debt_ceiling, assert_market_open,
+        assert_max_deposit, assert_migrated_asset, assert_not_globally_paused, assert_not_paused,
+        assert_position_sender, assert_price_guard, assert_revoked_collateral,
     },
+    error::ContractError,
     math::{
-        decimal_division, decimal_min, decimal_multiplication, decimal_subtraction, reverse_decimal,
+        decimal_conversion_rate, decimal_division, decimal_min, decimal_multiplication,
+        decimal_subtraction, reverse_decimal,
+    },
+    querier::{
+        load_asset_price_band, load_asset_price_band_through, load_asset_price_in_collateral,
+        load_collateral_asset_info, load_collateral_info, load_collateral_price_band,
+        load_pair_ask_info, PriceBand,
     },
-    querier::{load_asset_price, load_collateral_info},
     state::{
-        create_position, read_asset_config, read_config, read_position,
+        create_position, read_asset_config, read_asset_stats, read_collateral_stats, read_config,
+        read_fee_index, read_operator_approvals, read_pending_swap, read_position,
         read_position_idx, read_positions, read_positions_with_asset_indexer,
-        read_positions_with_user_indexer, remove_position, store_position,
-        store_position_idx, AssetConfig, Config, Position,
+        read_positions_with_user_indexer, read_price_guard, read_revoked_collateral,
+        remove_operator_approval, remove_pending_swap, remove_position, store_fee_index,
+        store_operator_approval, store_pending_swap, store_position, store_position_idx,
+        store_position_owner, store_price_guard, store_revoked_collateral, AssetConfig,
+        AssetStats, CollateralStats, Config, Debt, FeeIndex, OperatorApproval, PendingSwap,
+        Position, RevokedCollateral,
     }
 };
 
 use cw20::Cw20ExecuteMsg;
 use melange_protocol::{
+    collateral_oracle::CollateralInfoResponse,
     common::OrderBy,
-    lock::ExecuteMsg as LockExecuteMsg,
-    mint::{NextPositionIdxResponse, PositionResponse, PositionsResponse},
-    staking::ExecuteMsg as StakingExecuteMsg,
+    mint::{
+        NextPositionIdxResponse, Operation, OperatorApprovalResponse, OperatorApprovalsResponse,
+        PositionResponse, PositionsResponse,
+    },
+    pair::{Cw20HookMsg as PairCw20HookMsg, ExecuteMsg as PairExecuteMsg},
     asset::{Asset, AssetRaw, AssetInfo, AssetInfoRaw}
 };
+use sei_cosmwasm::SeiMsg;
+
+const SECONDS_PER_YEAR: u64 = 31_536_000u64;
+/// margin over the auction ratio within which a position can be self liquidated
+const SELF_LIQUIDATION_BUFFER: Decimal = Decimal::percent(10);
 
 
 pub fn open_position(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     sender: Addr,
     collateral: Asset,
     asset_info: AssetInfo,
     collateral_ratio: Decimal,
-) -> StdResult<Response> {
+) -> Result<Response<SeiMsg>, ContractError> {
     let config: Config = read_config(deps.storage)?;
+    assert_not_paused(deps.storage, Operation::Open, &asset_info.to_raw(deps.api)?)?;
     if collateral.amount.is_zero() {
-        return Err(StdError::generic_err("Wrong collateral"));
+        return Err(ContractError::WrongCollateral {});
     }
 
     // assert the collateral is listed and has not been migrated/revoked
     let collateral_info_raw: AssetInfoRaw = collateral.info.to_raw(deps.api)?;
     let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
-    let (collateral_price, collateral_multiplier) = assert_revoked_collateral(
-        load_collateral_info(deps.as_ref(), collateral_oracle, &collateral_info_raw, true)?,
-    )?;
+    let collateral_info: CollateralInfoResponse =
+        load_collateral_asset_info(deps.as_ref(), collateral_oracle.clone(), &collateral_info_raw)?;
+    let (collateral_price_band, collateral_multiplier) =
+        assert_revoked_collateral(load_collateral_price_band(
+            deps.as_ref(),
+            collateral_oracle,
+            &collateral_info_raw,
+            &collateral_info,
+            true,
+        )?)?;
+    let collateral_decimals: u8 = collateral_info.decimals;
 
     // assert asset migrated
     let asset_info_raw: AssetInfoRaw = asset_info.to_raw(deps.api)?;
-    let asset_token_raw = match asset_info_raw.clone() {
-        AssetInfoRaw::Token { contract_addr } => contract_addr,
-        _ => panic!("DO NOT ENTER HERE"),
-    };
-
-    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_token_raw)?;
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
     assert_migrated_asset(&asset_config)?;
-
-    if collateral_ratio
-        < decimal_multiplication(asset_config.min_collateral_ratio, collateral_multiplier)
-    {
-        return Err(StdError::generic_err(
-            "Can not open a position with low collateral ratio than minimum",
-        ));
+    assert_market_open(&asset_config, env.block.time.seconds())?;
+
+    let required_collateral_ratio: Decimal =
+        decimal_multiplication(asset_config.min_collateral_ratio, collateral_multiplier);
+    if collateral_ratio < required_collateral_ratio {
+        return Err(ContractError::LowCollateralRatio {
+            required: required_collateral_ratio,
+            actual: collateral_ratio,
+        });
     }
 
+    // the collateral is valued at the low end and the minted asset at the high end
+    // of their confidence bands
     let oracle: Addr = deps.api.addr_humanize(&config.oracle)?;
-    let asset_price: Decimal = load_asset_price(deps.as_ref(), oracle, &asset_info_raw, true)?;
-
-    let asset_price_in_collateral_asset = decimal_division(collateral_price, asset_price);
+    let (collateral_price_in_asset, asset_price): (Decimal, Decimal) =
+        match load_asset_price_in_collateral(
+            deps.as_ref(),
+            oracle.clone(),
+            &asset_info_raw,
+            &collateral_info_raw,
+            true,
+        )? {
+            // the oracle quotes the asset in the collateral's denom
+            Some(asset_price_band) => (
+                decimal_conversion_rate(
+                    Decimal::one(),
+                    collateral_decimals,
+                    asset_price_band.high,
+                    asset_config.decimals,
+                ),
+                decimal_multiplication(asset_price_band.mid, collateral_price_band.mid),
+            ),
+            // otherwise both prices are crossed through base denom
+            None => {
+                let asset_price_band: PriceBand =
+                    load_asset_price_band(deps.as_ref(), oracle, &asset_info_raw, true)?;
+                (
+                    decimal_conversion_rate(
+                        collateral_price_band.low,
+                        collateral_decimals,
+                        asset_price_band.high,
+                        asset_config.decimals,
+                    ),
+                    asset_price_band.mid,
+                )
+            }
+        };
+    check_price_guard(
+        deps.branch(),
+        &env,
+        &collateral_info_raw,
+        collateral_price_band.mid,
+    )?;
+    check_price_guard(deps.branch(), &env, &asset_info_raw, asset_price)?;
+
+    // Open fee is charged on the value to be minted and taken from the collateral
+    let open_fee = Asset {
+        info: collateral.info.clone(),
+        amount: collateral.amount
+            * reverse_decimal(collateral_ratio)
+            * asset_config.fee_schedule.open_fee_rate,
+    };
+    let collateral_amount: Uint128 = collateral.amount.checked_sub(open_fee.amount)?;
 
     // Convert collateral to mint amount
     let mint_amount =
-        collateral.amount * asset_price_in_collateral_asset * reverse_decimal(collateral_ratio);
+        collateral_amount * collateral_price_in_asset * reverse_decimal(collateral_ratio);
     if mint_amount.is_zero() {
-        return Err(StdError::generic_err("collateral is too small"));
+        return Err(ContractError::CollateralTooSmall {});
     }
 
+    // assert the debt ceiling of the asset and the deposit cap of the collateral
+    let asset_stats: AssetStats = read_asset_stats(deps.storage, &asset_info_raw)?;
+    assert_debt_ceiling(&asset_config, asset_stats.total_minted + mint_amount)?;
+
+    let collateral_stats: CollateralStats =
+        read_collateral_stats(deps.storage, &collateral_info_raw)?;
+    assert_max_deposit(
+        collateral_info.max_deposit,
+        collateral_stats.total_deposited + collateral_amount,
+    )?;
+
     let position_idx = read_position_idx(deps.storage)?;
     let asset_info_raw = asset_info.to_raw(deps.api)?;
 
+    // new debt starts accruing from the current fee index
+    let fee_index: Decimal = accrue_fee_index(
+        deps.storage,
+        &asset_info_raw,
+        asset_config.stability_fee_rate,
+        env.block.time.seconds(),
+    )?;
+
     create_position(
         deps.storage,
         position_idx,
         &Position {
             idx: position_idx,
             owner: deps.api.addr_canonicalize(sender.as_str())?,
-            collateral: AssetRaw {
-                amount: collateral.amount,
+            collateral: vec![AssetRaw {
+                amount: collateral_amount,
                 info: collateral_info_raw,
-            },
-            asset: AssetRaw {
-                amount: mint_amount,
+            }],
+            debt: vec![Debt {
                 info: asset_info_raw,
-            },
+                amount: mint_amount,
+                fee_index,
+            }],
         },
     )?;
 
-    let asset_token = deps.api.addr_humanize(&asset_config.token)?.to_string();
-    let messages: Vec<CosmosMsg> = {
-        vec![CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: asset_token,
-            funds: vec![],
-            msg: to_binary(&Cw20ExecuteMsg::Mint {
-                recipient: sender.to_string(),
-                amount: mint_amount,
-            })?,
-        })]
-    };
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = mint_asset_msgs(
+        &Asset {
+            info: asset_info.clone(),
+            amount: mint_amount,
+        },
+        &sender,
+    )?;
+
+    if !open_fee.amount.is_zero() {
+        messages.push(
+            open_fee
+                .clone()
+                .into_msg(deps.api.addr_humanize(&config.collector)?)?,
+        );
+    }
 
     store_position_idx(deps.storage, position_idx + Uint128::from(1u128))?;
     Ok(Response::new()
@@ -121,406 +220,2038 @@ pub fn open_position(
                 mint_amount.to_string() + &asset_info.to_string(),
             ),
             attr("collateral_amount", collateral.to_string()),
+            attr("open_fee", open_fee.to_string()),
         ])
         .add_messages(messages))
 }
 
 pub fn deposit(
-    deps: DepsMut,
+    mut deps: DepsMut,
+    env: Env,
     sender: Addr,
     position_idx: Uint128,
     collateral: Asset,
-) -> StdResult<Response> {
+) -> Result<Response<SeiMsg>, ContractError> {
+    assert_not_globally_paused(deps.storage, Operation::Deposit)?;
     let config: Config = read_config(deps.storage)?;
     let mut position: Position = read_position(deps.storage, position_idx)?;
-    let position_owner = deps.api.addr_humanize(&position.owner)?;
-    if sender != position_owner {
-        return Err(StdError::generic_err("unauthorized"));
+    assert_position_sender(
+        deps.storage,
+        &position,
+        &deps.api.addr_canonicalize(sender.as_str())?,
+        Operation::Deposit,
+        env.block.time.seconds(),
+    )?;
+
+    // assert no minted asset is paused or migrated
+    for debt in position.debt.iter() {
+        assert_not_paused(deps.storage, Operation::Deposit, &debt.info)?;
+        assert_migrated_asset(&read_asset_config(deps.storage, &debt.info)?)?;
     }
 
-    // Check the given collateral has same asset info
-    // with position's collateral token
-    // also Check the collateral amount is non-zero
-    assert_collateral(deps.as_ref(), &position, &collateral)?;
+    // Check the collateral amount is non-zero
+    assert_collateral(&collateral)?;
 
     // assert the collateral is listed and has not been migrated/revoked
+    let collateral_info_raw: AssetInfoRaw = collateral.info.to_raw(deps.api)?;
     let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+    let collateral_info: CollateralInfoResponse =
+        load_collateral_asset_info(deps.as_ref(), collateral_oracle.clone(), &collateral_info_raw)?;
     assert_revoked_collateral(load_collateral_info(
         deps.as_ref(),
         collateral_oracle,
-        &position.collateral.info,
+        &collateral_info_raw,
+        &collateral_info,
         false,
     )?)?;
 
-    // assert asset migrated
-    match position.asset.info.clone() {
-        AssetInfoRaw::Token { contract_addr } => {
-            assert_migrated_asset(&read_asset_config(deps.storage, &contract_addr)?)?
-        }
-        _ => panic!("DO NOT ENTER HERE"),
-    };
+    // assert the deposit cap of the collateral
+    let collateral_stats: CollateralStats =
+        read_collateral_stats(deps.storage, &collateral_info_raw)?;
+    assert_max_deposit(
+        collateral_info.max_deposit,
+        collateral_stats.total_deposited + collateral.amount,
+    )?;
+
+    // Increase collateral amount; a new collateral type joins the basket
+    add_collateral(&mut position, collateral_info_raw, collateral.amount);
+
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+    let messages: Vec<CosmosMsg<SeiMsg>> = transfer_msgs(
+        &stability_fee,
+        deps.api.addr_humanize(&config.collector)?,
+    )?;
 
-    // Increase collateral amount
-    position.collateral.amount += collateral.amount;
     store_position(deps.storage, position_idx, &position)?;
 
-    Ok(Response::new().add_attributes(vec![
-        attr("action", "deposit"),
-        attr("position_idx", position_idx.to_string()),
-        attr("deposit_amount", collateral.to_string()),
-    ]))
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "deposit"),
+            attr("position_idx", position_idx.to_string()),
+            attr("deposit_amount", collateral.to_string()),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+        ]))
 }
 
 pub fn withdraw(
-    deps: DepsMut,
+    mut deps: DepsMut,
+    env: Env,
     sender: Addr,
     position_idx: Uint128,
     collateral: Option<Asset>,
-) -> StdResult<Response> {
+) -> Result<Response<SeiMsg>, ContractError> {
+    assert_not_globally_paused(deps.storage, Operation::Withdraw)?;
     let config: Config = read_config(deps.storage)?;
     let mut position: Position = read_position(deps.storage, position_idx)?;
     let position_owner = deps.api.addr_humanize(&position.owner)?;
-    if sender != position_owner {
-        return Err(StdError::generic_err("unauthorized"));
+    assert_position_sender(
+        deps.storage,
+        &position,
+        &deps.api.addr_canonicalize(sender.as_str())?,
+        Operation::Withdraw,
+        env.block.time.seconds(),
+    )?;
+
+    // ignore multiplier when every minted asset is delisted
+    let mut delisted: bool = true;
+    for debt in position.debt.iter() {
+        assert_not_paused(deps.storage, Operation::Withdraw, &debt.info)?;
+        let asset_config: AssetConfig = read_asset_config(deps.storage, &debt.info)?;
+        assert_market_open(&asset_config, env.block.time.seconds())?;
+        delisted &= asset_config.end_price.is_some();
     }
 
-    // if collateral is not provided, withraw all collateral
-    let collateral: Asset = if let Some(collateral) = collateral {
-        // Check the given collateral has same asset info
-        // with position's collateral token
-        // also Check the collateral amount is non-zero
-        assert_collateral(deps.as_ref(), &position, &collateral)?;
-
-        if position.collateral.amount < collateral.amount {
-            return Err(StdError::generic_err(
-                "Cannot withdraw more than you provide",
-            ));
-        }
+    // the fee is settled first so it can not eat into the withdrawn collateral
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
 
-        collateral
+    // if collateral is not provided, withraw all collateral
+    let withdrawn: Vec<Asset> = if let Some(collateral) = collateral {
+        // Check the collateral amount is non-zero
+        // and the position holds the given collateral
+        assert_collateral(&collateral)?;
+        subtract_collateral(
+            &mut position,
+            &collateral.info.to_raw(deps.api)?,
+            collateral.amount,
+        )?;
+        vec![collateral]
     } else {
-        position.collateral.to_normal(deps.api)?
-    };
-
-    let asset_token_raw = match position.asset.info.clone() {
-        AssetInfoRaw::Token { contract_addr } => contract_addr,
-        _ => panic!("DO NOT ENTER HERE"),
+        to_normal_assets(deps.api, &std::mem::take(&mut position.collateral))?
     };
 
-    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_token_raw)?;
-    let oracle: Addr = deps.api.addr_humanize(&config.oracle)?;
-    let asset_price: Decimal = load_asset_price(deps.as_ref(), oracle, &position.asset.info, true)?;
-
     // Fetch collateral info from collateral oracle
-    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
-    let (collateral_price, mut collateral_multiplier, _collateral_is_revoked) =
-        load_collateral_info(
-            deps.as_ref(),
-            collateral_oracle,
-            &position.collateral.info,
-            true,
-        )?;
-
-    // ignore multiplier for delisted assets
-    if asset_config.end_price.is_some() {
-        collateral_multiplier = Decimal::one();
+    let mut valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Conservative)?;
+    check_price_guards(deps.branch(), &env, &valuation)?;
+    record_revoked_collaterals(deps.storage, &valuation, env.block.time.seconds())?;
+    if delisted {
+        for rate in valuation.collateral.iter_mut() {
+            rate.multiplier = Decimal::one();
+        }
     }
 
-    // Compute new collateral amount
-    let collateral_amount: Uint128 = position.collateral.amount.checked_sub(collateral.amount)?;
-
-    // Convert asset to collateral unit
-    let asset_value_in_collateral_asset: Uint128 =
-        position.asset.amount * decimal_division(asset_price, collateral_price);
-
     // Check minimum collateral ratio is satisfied
-    if asset_value_in_collateral_asset * asset_config.min_collateral_ratio * collateral_multiplier
-        > collateral_amount
-    {
-        return Err(StdError::generic_err(
-            "Cannot withdraw collateral over than minimum collateral ratio",
-        ));
-    }
+    assert_collateral_ratio(&position, &valuation)?;
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-
-    position.collateral.amount = collateral_amount;
-
-    if position.collateral.amount == Uint128::zero() && position.asset.amount == Uint128::zero() {
+    let mut messages: Vec<CosmosMsg<SeiMsg>> =
+        transfer_msgs(&withdrawn, position_owner)?;
+    messages.extend(transfer_msgs(
+        &stability_fee,
+        deps.api.addr_humanize(&config.collector)?,
+    )?);
 
+    if position.collateral.is_empty() && position.debt.is_empty() {
+        remove_position(deps.storage, position_idx)?;
+    } else {
         store_position(deps.storage, position_idx, &position)?;
     }
 
     Ok(Response::new()
-        .add_messages(
-            vec![
-                vec![collateral.clone().into_msg(&deps.querier, position_owner)?],
-                messages,
-            ]
-                .concat(),
-        )
+        .add_messages(messages)
         .add_attributes(vec![
             attr("action", "withdraw"),
             attr("position_idx", position_idx.to_string()),
-            attr("withdraw_amount", collateral.to_string()),
+            attr("withdraw_amount", assets_to_string(&withdrawn)),
+            attr("stability_fee", assets_to_string(&stability_fee)),
         ]))
 }
 
-pub fn mint(
-    deps: DepsMut,
+/// Deposit a new collateral and return old collateral in one step;
+/// all the other collateral types are returned if `old_collateral` is not given
+pub fn swap_collateral(
+    mut deps: DepsMut,
     env: Env,
     sender: Addr,
     position_idx: Uint128,
-    asset: Asset,
-) -> StdResult<Response> {
+    new_collateral: Asset,
+    old_collateral: Option<Asset>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    for operation in [Operation::Deposit, Operation::Withdraw] {
+        assert_not_globally_paused(deps.storage, operation)?;
+    }
     let config: Config = read_config(deps.storage)?;
-    let mint_amount = asset.amount;
-
     let mut position: Position = read_position(deps.storage, position_idx)?;
     let position_owner = deps.api.addr_humanize(&position.owner)?;
-    if sender != position_owner {
-        return Err(StdError::generic_err("unauthorized"));
+    let sender_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    for operation in [Operation::Deposit, Operation::Withdraw] {
+        assert_position_sender(
+            deps.storage,
+            &position,
+            &sender_raw,
+            operation,
+            env.block.time.seconds(),
+        )?;
+        for debt in position.debt.iter() {
+            assert_not_paused(deps.storage, operation, &debt.info)?;
+        }
+    }
+
+    // collateral can not be moved against debts whose market is closed
+    for debt in position.debt.iter() {
+        let asset_config: AssetConfig = read_asset_config(deps.storage, &debt.info)?;
+        assert_market_open(&asset_config, env.block.time.seconds())?;
     }
 
-    assert_asset(deps.as_ref(), &position, &asset)?;
+    // assert the new collateral is listed and has not been migrated/revoked
+    assert_collateral(&new_collateral)?;
+    let new_collateral_info_raw: AssetInfoRaw = new_collateral.info.to_raw(deps.api)?;
+    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+    let new_collateral_info: CollateralInfoResponse = load_collateral_asset_info(
+        deps.as_ref(),
+        collateral_oracle.clone(),
+        &new_collateral_info_raw,
+    )?;
+    assert_revoked_collateral(load_collateral_info(
+        deps.as_ref(),
+        collateral_oracle,
+        &new_collateral_info_raw,
+        &new_collateral_info,
+        false,
+    )?)?;
+
+    // assert the deposit cap of the new collateral
+    let collateral_stats: CollateralStats =
+        read_collateral_stats(deps.storage, &new_collateral_info_raw)?;
+    assert_max_deposit(
+        new_collateral_info.max_deposit,
+        collateral_stats.total_deposited + new_collateral.amount,
+    )?;
 
-    let asset_token_raw = match position.asset.info.clone() {
-        AssetInfoRaw::Token { contract_addr } => contract_addr,
-        _ => panic!("DO NOT ENTER HERE"),
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+
+    let returned: Vec<Asset> = match old_collateral {
+        Some(old_collateral) => {
+            if old_collateral.info.equal(&new_collateral.info) {
+                return Err(ContractError::WrongCollateral {});
+            }
+
+            assert_collateral(&old_collateral)?;
+            subtract_collateral(
+                &mut position,
+                &old_collateral.info.to_raw(deps.api)?,
+                old_collateral.amount,
+            )?;
+            vec![old_collateral]
+        }
+        None => {
+            let (kept, returned): (Vec<AssetRaw>, Vec<AssetRaw>) =
+                std::mem::take(&mut position.collateral)
+                    .into_iter()
+                    .partition(|c| c.info.equal(&new_collateral_info_raw));
+            position.collateral = kept;
+            to_normal_assets(deps.api, &returned)?
+        }
     };
+    add_collateral(&mut position, new_collateral_info_raw, new_collateral.amount);
 
-    // assert the asset migrated
-    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_token_raw)?;
-    assert_migrated_asset(&asset_config)?;
+    // Check minimum collateral ratio is satisfied with the new collateral
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Conservative)?;
+    check_price_guards(deps.branch(), &env, &valuation)?;
+    record_revoked_collaterals(deps.storage, &valuation, env.block.time.seconds())?;
+    assert_collateral_ratio(&position, &valuation)?;
 
-    // assert the collateral is listed and has not been migrated/revoked
-    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
-    let (collateral_price, collateral_multiplier) =
-        assert_revoked_collateral(load_collateral_info(
-            deps.as_ref(),
-            collateral_oracle,
-            &position.collateral.info,
-            true,
-        )?)?;
+    store_position(deps.storage, position_idx, &position)?;
 
-    let oracle: Addr = deps.api.addr_humanize(&config.oracle)?;
-    let asset_price: Decimal = load_asset_price(deps.as_ref(), oracle, &position.asset.info, true)?;
+    let mut messages: Vec<CosmosMsg<SeiMsg>> =
+        transfer_msgs(&returned, position_owner)?;
+    messages.extend(transfer_msgs(
+        &stability_fee,
+        deps.api.addr_humanize(&config.collector)?,
+    )?);
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "swap_collateral"),
+            attr("position_idx", position_idx.to_string()),
+            attr("deposit_amount", new_collateral.to_string()),
+            attr("withdraw_amount", assets_to_string(&returned)),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+        ]))
+}
 
-    // Compute new asset amount
-    let asset_amount: Uint128 = mint_amount + position.asset.amount;
+/// Mint any registered asset against the position;
+/// an asset the position has not minted yet is added to its debts
+pub fn mint(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    asset: Asset,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let mint_amount = asset.amount;
+    let asset_info_raw: AssetInfoRaw = asset.info.to_raw(deps.api)?;
 
-    // Convert asset to collateral unit
-    let asset_value_in_collateral_asset: Uint128 =
-        asset_amount * decimal_division(asset_price, collateral_price);
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    assert_not_paused(deps.storage, Operation::Mint, &asset_info_raw)?;
+    let position_owner = deps.api.addr_humanize(&position.owner)?;
+    assert_position_sender(
+        deps.storage,
+        &position,
+        &deps.api.addr_canonicalize(sender.as_str())?,
+        Operation::Mint,
+        env.block.time.seconds(),
+    )?;
 
-    // Check minimum collateral ratio is satisfied
-    if asset_value_in_collateral_asset * asset_config.min_collateral_ratio * collateral_multiplier
-        > position.collateral.amount
-    {
-        return Err(StdError::generic_err(
-            "Cannot mint asset over than min collateral ratio",
-        ));
+    if mint_amount.is_zero() {
+        return Err(ContractError::WrongAsset {});
     }
 
-    position.asset.amount += mint_amount;
-    store_position(deps.storage, position_idx, &position)?;
+    // settle the fee on the old debt before it grows
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+
+    // Check minimum collateral ratio is satisfied with the new asset amount
+    let (valuation, mint_fee) = increase_debt(
+        deps.branch(),
+        &env,
+        &config,
+        &mut position,
+        &asset_info_raw,
+        mint_amount,
+    )?;
+    assert_collateral_ratio(&position, &valuation)?;
 
-    let asset_token = deps.api.addr_humanize(&asset_config.token)?;
+    store_position(deps.storage, position_idx, &position)?;
 
-    let messages: Vec<CosmosMsg> = {
-        vec![CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: deps.api.addr_humanize(&asset_config.token)?.to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Mint {
-                amount: mint_amount,
-                recipient: position_owner.to_string(),
-            })?,
-            funds: vec![],
-        })]
-    };
+    let collector: Addr = deps.api.addr_humanize(&config.collector)?;
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = mint_asset_msgs(&asset, &position_owner)?;
+    messages.extend(transfer_msgs(&stability_fee, collector.clone())?);
+    messages.extend(transfer_msgs(&mint_fee, collector)?);
 
     Ok(Response::new()
         .add_attributes(vec![
             attr("action", "mint"),
             attr("position_idx", position_idx.to_string()),
             attr("mint_amount", asset.to_string()),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+            attr("mint_fee", assets_to_string(&mint_fee)),
         ])
         .add_messages(messages))
 }
 
-pub fn query_position(deps: Deps, position_idx: Uint128) -> StdResult<PositionResponse> {
-    let position: Position = read_position(deps.storage, position_idx)?;
-    let resp = PositionResponse {
-        idx: position.idx,
-        owner: deps.api.addr_humanize(&position.owner)?.to_string(),
-        collateral: position.collateral.to_normal(deps.api)?,
-        asset: position.asset.to_normal(deps.api)?,
-    };
-
-    Ok(resp)
-}
-
-pub fn burn(
-    deps: DepsMut,
+/// Mint the asset and sell it on the pair for collateral within one transaction;
+/// the bought collateral is deposited and the ratio checked in the swap reply
+#[allow(clippy::too_many_arguments)]
+pub fn leverage(
+    mut deps: DepsMut,
     env: Env,
     sender: Addr,
     position_idx: Uint128,
-    asset: Asset,
-) -> StdResult<Response> {
-    let burn_amount = asset.amount;
+    mint_amount: Asset,
+    pair: String,
+    min_out: Uint128,
+) -> Result<Response<SeiMsg>, ContractError> {
+    // the pair must not start another swap over the pending one
+    if read_pending_swap(deps.storage)?.is_some() {
+        return Err(ContractError::SwapInProgress {});
+    }
+
     let config: Config = read_config(deps.storage)?;
+    let asset_info_raw: AssetInfoRaw = mint_amount.info.to_raw(deps.api)?;
     let mut position: Position = read_position(deps.storage, position_idx)?;
-    let position_owner = deps.api.addr_humanize(&position.owner)?;
-    let collateral_info: AssetInfo = position.collateral.info.to_normal(deps.api)?;
-
-    // Check the asset has same token with position asset
-    // also Check burn amount is non-zero
-    assert_asset(deps.as_ref(), &position, &asset)?;
-
-    let asset_token_raw = match position.asset.info.clone() {
-        AssetInfoRaw::Token { contract_addr } => contract_addr,
-        _ => panic!("DO NOT ENTER HERE"),
-    };
-
-    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_token_raw)?;
-    if position.asset.amount < burn_amount {
-        return Err(StdError::generic_err(
-            "Cannot burn asset more than you mint",
-        ));
+    let sender_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    for operation in [Operation::Mint, Operation::Deposit] {
+        assert_position_sender(
+            deps.storage,
+            &position,
+            &sender_raw,
+            operation,
+            env.block.time.seconds(),
+        )?;
+        assert_not_paused(deps.storage, operation, &asset_info_raw)?;
     }
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    let mut attributes: Vec<Attribute> = vec![];
+    if mint_amount.amount.is_zero() {
+        return Err(ContractError::WrongAsset {});
+    }
 
-    // fetch collateral info from collateral oracle
+    // the pair has to trade the minted asset for a listed collateral
+    let pair: Addr = deps.api.addr_validate(&pair)?;
+    let ask_info: AssetInfo = load_pair_ask_info(&deps.querier, pair.clone(), &mint_amount.info)?;
+    let ask_info_raw: AssetInfoRaw = ask_info.to_raw(deps.api)?;
     let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
-    let (collateral_price, _collateral_multiplier, _collateral_is_revoked) = load_collateral_info(
+    let ask_collateral_info: CollateralInfoResponse =
+        load_collateral_asset_info(deps.as_ref(), collateral_oracle.clone(), &ask_info_raw)?;
+    assert_revoked_collateral(load_collateral_info(
         deps.as_ref(),
         collateral_oracle,
-        &position.collateral.info,
-        true,
+        &ask_info_raw,
+        &ask_collateral_info,
+        false,
+    )?)?;
+
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+    let (_, mint_fee) = increase_debt(
+        deps.branch(),
+        &env,
+        &config,
+        &mut position,
+        &asset_info_raw,
+        mint_amount.amount,
+    )?;
+    store_position(deps.storage, position_idx, &position)?;
+
+    let prev_deposited: Uint128 =
+        read_collateral_stats(deps.storage, &ask_info_raw)?.total_deposited;
+    store_pending_swap(
+        deps.storage,
+        &PendingSwap {
+            position_idx,
+            prev_balance: ask_info.query_pool(
+                &deps.querier,
+                deps.api,
+                env.contract.address.clone(),
+            )?,
+            prev_deposited,
+            ask_info: ask_info_raw,
+            min_out,
+            self_liquidation: false,
+            prev_ratio: None,
+        },
     )?;
 
-    // If the collateral is default denom asset and the asset is deprecated,
-    // anyone can execute burn the asset to any position without permission
-    let mut close_position: bool = false;
+    let collector: Addr = deps.api.addr_humanize(&config.collector)?;
+    let mut messages: Vec<CosmosMsg<SeiMsg>> =
+        mint_asset_msgs(&mint_amount, &env.contract.address)?;
+    messages.extend(transfer_msgs(&stability_fee, collector.clone())?);
+    messages.extend(transfer_msgs(&mint_fee, collector)?);
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_submessage(SubMsg::reply_on_success(
+            swap_msg(&mint_amount, &pair)?,
+            LEVERAGE_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr("action", "leverage"),
+            attr("position_idx", position_idx.to_string()),
+            attr("mint_amount", mint_amount.to_string()),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+            attr("mint_fee", assets_to_string(&mint_fee)),
+        ]))
+}
 
-    if let Some(end_price) = asset_config.end_price {
-        let asset_price: Decimal = end_price;
+/// Deposit the collateral bought by the leverage swap
+pub fn leverage_reply(deps: DepsMut, env: Env) -> Result<Response<SeiMsg>, ContractError> {
+    let pending_swap: PendingSwap = read_pending_swap(deps.storage)?
+        .ok_or_else(|| StdError::not_found("PendingSwap"))?;
+    remove_pending_swap(deps.storage);
+    let received: Uint128 = swapped_amount(deps.as_ref(), &env, &pending_swap)?;
 
-        let collateral_price_in_asset = decimal_division(asset_price, collateral_price);
+    let config: Config = read_config(deps.storage)?;
+    let mut position: Position = read_position(deps.storage, pending_swap.position_idx)?;
 
-        // Burn deprecated asset to receive collaterals back
-        let conversion_rate =
-            Decimal::from_ratio(position.collateral.amount, position.asset.amount);
-        let mut refund_collateral = Asset {
-            info: collateral_info.clone(),
-            amount: std::cmp::min(
-                burn_amount * collateral_price_in_asset,
-                burn_amount * conversion_rate,
-            ),
-        };
+    // assert the deposit cap of the collateral
+    let collateral_stats: CollateralStats =
+        read_collateral_stats(deps.storage, &pending_swap.ask_info)?;
+    let collateral_info: CollateralInfoResponse = load_collateral_asset_info(
+        deps.as_ref(),
+        deps.api.addr_humanize(&config.collateral_oracle)?,
+        &pending_swap.ask_info,
+    )?;
+    assert_max_deposit(
+        collateral_info.max_deposit,
+        collateral_stats.total_deposited + received,
+    )?;
 
-        position.asset.amount = position.asset.amount.checked_sub(burn_amount).unwrap();
-        position.collateral.amount = position
-            .collateral
-            .amount
-            .checked_sub(refund_collateral.amount)
-            .unwrap();
+    add_collateral(&mut position, pending_swap.ask_info.clone(), received);
 
-        // due to rounding, include 1
-        if position.collateral.amount <= Uint128::from(1u128)
-            && position.asset.amount == Uint128::zero()
-        {
-            close_position = true;
-            remove_position(deps.storage, position_idx)?;
-        } else {
-            store_position(deps.storage, position_idx, &position)?;
-        }
+    // Check minimum collateral ratio is satisfied with the minted debt
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Conservative)?;
+    assert_collateral_ratio(&position, &valuation)?;
 
-        // Subtract protocol fee from refunded collateral
-        let protocol_fee = Asset {
-            info: collateral_info,
-            amount: burn_amount * collateral_price_in_asset * config.protocol_fee_rate,
-        };
+    store_position(deps.storage, pending_swap.position_idx, &position)?;
 
-        if !protocol_fee.amount.is_zero() {
-            messages.push(
-                protocol_fee
-                    .clone()
-                    .into_msg(&deps.querier, deps.api.addr_humanize(&config.collector)?)?,
-            );
-            refund_collateral.amount = refund_collateral
-                .amount
-                .checked_sub(protocol_fee.amount)
-                .unwrap();
-        }
-        attributes.push(attr("protocol_fee", protocol_fee.to_string()));
+    let deposit_amount = Asset {
+        info: pending_swap.ask_info.to_normal(deps.api)?,
+        amount: received,
+    };
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "leverage_deposit"),
+        attr("position_idx", pending_swap.position_idx.to_string()),
+        attr("deposit_amount", deposit_amount.to_string()),
+    ]))
+}
 
-        // Refund collateral msg
-        messages.push(refund_collateral.clone().into_msg(&deps.querier, sender)?);
+/// Withdraw collateral and sell it on the pair for a minted asset within one transaction;
+/// the bought asset is burned and the ratio checked in the swap reply
+pub fn deleverage(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    collateral_amount: Asset,
+    pair: String,
+    min_out: Uint128,
+) -> Result<Response<SeiMsg>, ContractError> {
+    sell_collateral(
+        deps,
+        env,
+        sender,
+        position_idx,
+        collateral_amount,
+        pair,
+        min_out,
+        false,
+    )
+}
 
-        attributes.push(attr(
-            "refund_collateral_amount",
-            refund_collateral.to_string(),
-        ));
-    } else {
-        if sender != position_owner {
-            return Err(StdError::generic_err("unauthorized"));
-        }
-        let oracle = deps.api.addr_humanize(&config.oracle)?;
-        let asset_price: Decimal =
-            load_asset_price(deps.as_ref(), oracle, &asset.info.to_raw(deps.api)?, true)?;
-        let collateral_price_in_asset: Decimal = decimal_division(asset_price, collateral_price);
+/// Pay down the debt of a position near liquidation by selling its own collateral;
+/// charged the self liquidation fee instead of the auction discount, and the
+/// ratio only has to improve rather than reach the minimum. Healthier positions
+/// deleverage instead, paying the burn fee
+pub fn self_liquidate(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    collateral_amount: Asset,
+    pair: String,
+    min_out: Uint128,
+) -> Result<Response<SeiMsg>, ContractError> {
+    sell_collateral(
+        deps,
+        env,
+        sender,
+        position_idx,
+        collateral_amount,
+        pair,
+        min_out,
+        true,
+    )
+}
 
-        // Subtract the protocol fee from the position's collateral
-        let protocol_fee = Asset {
-            info: collateral_info,
-            amount: burn_amount * collateral_price_in_asset * config.protocol_fee_rate,
-        };
+#[allow(clippy::too_many_arguments)]
+fn sell_collateral(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    collateral_amount: Asset,
+    pair: String,
+    min_out: Uint128,
+    self_liquidation: bool,
+) -> Result<Response<SeiMsg>, ContractError> {
+    for operation in [Operation::Withdraw, Operation::Burn] {
+        assert_not_globally_paused(deps.storage, operation)?;
+    }
 
-        if !protocol_fee.amount.is_zero() {
-            messages.push(
-                protocol_fee
-                    .clone()
-                    .into_msg(&deps.querier, deps.api.addr_humanize(&config.collector)?)?,
-            );
-            position.collateral.amount = position
-                .collateral
-                .amount
-                .checked_sub(protocol_fee.amount)?
+    // the pair must not start another swap over the pending one
+    if read_pending_swap(deps.storage)?.is_some() {
+        return Err(ContractError::SwapInProgress {});
+    }
+
+    let config: Config = read_config(deps.storage)?;
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    let sender_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    for operation in [Operation::Withdraw, Operation::Burn] {
+        assert_position_sender(
+            deps.storage,
+            &position,
+            &sender_raw,
+            operation,
+            env.block.time.seconds(),
+        )?;
+        for debt in position.debt.iter() {
+            assert_not_paused(deps.storage, operation, &debt.info)?;
         }
-        attributes.push(attr("protocol_fee", protocol_fee.to_string()));
+    }
 
-        // Update asset amount
-        position.asset.amount = position.asset.amount.checked_sub(burn_amount).unwrap();
-        store_position(deps.storage, position_idx, &position)?;
+    // collateral can not be moved against debts whose market is closed
+    for debt in position.debt.iter() {
+        let asset_config: AssetConfig = read_asset_config(deps.storage, &debt.info)?;
+        assert_market_open(&asset_config, env.block.time.seconds())?;
     }
 
-    let asset_token = deps.api.addr_humanize(&asset_config.token)?;
+    // Check the collateral amount is non-zero
+    assert_collateral(&collateral_amount)?;
 
-    Ok(Response::new()
-        .add_messages(
-            vec![
-                vec![CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: asset_token.to_string(),
-                    msg: to_binary(&Cw20ExecuteMsg::Burn {
-                        amount: burn_amount,
-                    })?,
-                    funds: vec![],
-                })],
-                messages,
-            ]
-                .concat(),
-        )
-        .add_attributes(
-            vec![
-                vec![
-                    attr("action", "burn"),
-                    attr("position_idx", position_idx.to_string()),
-                    attr("burn_amount", asset.to_string()),
-                ],
-                attributes,
-            ]
-                .concat(),
-        ))
+    // the pair has to trade the collateral for an asset the position has minted
+    let pair: Addr = deps.api.addr_validate(&pair)?;
+    let ask_info: AssetInfo =
+        load_pair_ask_info(&deps.querier, pair.clone(), &collateral_amount.info)?;
+    let ask_info_raw: AssetInfoRaw = ask_info.to_raw(deps.api)?;
+    if position.find_debt(&ask_info_raw).is_none() {
+        return Err(ContractError::WrongAsset {});
+    }
+
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Conservative)?;
+    check_price_guards(deps.branch(), &env, &valuation)?;
+
+    // a self liquidation is only open to positions near liquidation and has to
+    // leave the position at least as collateralized as it was
+    let prev_ratio: Option<Decimal> = if self_liquidation {
+        Some(assert_near_liquidation(
+            deps.storage,
+            &config,
+            &position,
+            &valuation,
+            env.block.time.seconds(),
+        )?)
+    } else {
+        None
+    };
+
+    subtract_collateral(
+        &mut position,
+        &collateral_amount.info.to_raw(deps.api)?,
+        collateral_amount.amount,
+    )?;
+    store_position(deps.storage, position_idx, &position)?;
+
+    let prev_deposited: Uint128 =
+        read_collateral_stats(deps.storage, &ask_info_raw)?.total_deposited;
+    store_pending_swap(
+        deps.storage,
+        &PendingSwap {
+            position_idx,
+            prev_balance: ask_info.query_pool(
+                &deps.querier,
+                deps.api,
+                env.contract.address.clone(),
+            )?,
+            prev_deposited,
+            ask_info: ask_info_raw,
+            min_out,
+            self_liquidation,
+            prev_ratio,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_messages(transfer_msgs(
+            &stability_fee,
+            deps.api.addr_humanize(&config.collector)?,
+        )?)
+        .add_submessage(SubMsg::reply_on_success(
+            swap_msg(&collateral_amount, &pair)?,
+            DELEVERAGE_REPLY_ID,
+        ))
+        .add_attributes(vec![
+            attr(
+                "action",
+                if self_liquidation {
+                    "self_liquidate"
+                } else {
+                    "deleverage"
+                },
+            ),
+            attr("position_idx", position_idx.to_string()),
+            attr("withdraw_amount", collateral_amount.to_string()),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+        ]))
+}
+
+/// Burn the asset bought by the deleverage or self liquidation swap;
+/// whatever exceeds the debt goes to the owner
+pub fn deleverage_reply(mut deps: DepsMut, env: Env) -> Result<Response<SeiMsg>, ContractError> {
+    let pending_swap: PendingSwap = read_pending_swap(deps.storage)?
+        .ok_or_else(|| StdError::not_found("PendingSwap"))?;
+    remove_pending_swap(deps.storage);
+    let received: Uint128 = swapped_amount(deps.as_ref(), &env, &pending_swap)?;
+
+    let config: Config = read_config(deps.storage)?;
+    let position_idx: Uint128 = pending_swap.position_idx;
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    let position_owner: Addr = deps.api.addr_humanize(&position.owner)?;
+
+    let asset_info_raw: AssetInfoRaw = pending_swap.ask_info;
+    let debt_amount: Uint128 = assert_asset(&position, &asset_info_raw, received)?;
+    let burn_amount: Uint128 = std::cmp::min(received, debt_amount);
+    let asset_info: AssetInfo = asset_info_raw.to_normal(deps.api)?;
+
+    // Burn fee is charged on the burned value and taken from the collateral;
+    // a self liquidation pays its own rate instead
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
+    assert_market_open(&asset_config, env.block.time.seconds())?;
+    let burn_fee_rate: Decimal = if pending_swap.self_liquidation {
+        asset_config.fee_schedule.self_liquidation_fee_rate
+    } else {
+        asset_config.fee_schedule.burn_fee_rate
+    };
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Conservative)?;
+    check_price_guards(deps.branch(), &env, &valuation)?;
+    let burn_fee_value: Uint128 =
+        burn_amount * burn_fee_rate * find_debt_rate(&valuation, &asset_info_raw)?.to_value;
+    let burn_fee: Vec<Asset> = to_normal_assets(
+        deps.api,
+        &take_collateral(&mut position.collateral, &valuation.collateral, burn_fee_value),
+    )?;
+
+    // Check minimum collateral ratio is satisfied with the withdrawn collateral,
+    // unless a self liquidation improved the ratio
+    reduce_debt(&mut position, &asset_info_raw, burn_amount)?;
+    let (collateral_value, debt_value, _) = value_position(&position, &valuation);
+    let improved: bool = match pending_swap.prev_ratio {
+        Some(prev_ratio) => {
+            debt_value.is_zero() || Decimal::from_ratio(collateral_value, debt_value) >= prev_ratio
+        }
+        None => false,
+    };
+    if !improved {
+        assert_collateral_ratio(&position, &valuation)?;
+    }
+
+    if position.collateral.is_empty() && position.debt.is_empty() {
+        remove_position(deps.storage, position_idx)?;
+    } else {
+        store_position(deps.storage, position_idx, &position)?;
+    }
+
+    let burned = Asset {
+        info: asset_info.clone(),
+        amount: burn_amount,
+    };
+    let excess = Asset {
+        info: asset_info,
+        amount: received - burn_amount,
+    };
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = vec![burn_asset_msg(&burned)?];
+    messages.extend(transfer_msgs(
+        &burn_fee,
+        deps.api.addr_humanize(&config.collector)?,
+    )?);
+    messages.extend(transfer_msgs(
+        std::slice::from_ref(&excess),
+        position_owner,
+    )?);
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr(
+                "action",
+                if pending_swap.self_liquidation {
+                    "self_liquidate_burn"
+                } else {
+                    "deleverage_burn"
+                },
+            ),
+            attr("position_idx", position_idx.to_string()),
+            attr("burn_amount", burned.to_string()),
+            attr("burn_fee", assets_to_string(&burn_fee)),
+            attr("refund_amount", excess.to_string()),
+        ]))
+}
+
+/// Amount bought by the pending swap, which has to reach its `min_out`
+fn swapped_amount(
+    deps: Deps,
+    env: &Env,
+    pending_swap: &PendingSwap,
+) -> Result<Uint128, ContractError> {
+    let balance: Uint128 = pending_swap.ask_info.to_normal(deps.api)?.query_pool(
+        &deps.querier,
+        deps.api,
+        env.contract.address.clone(),
+    )?;
+    // collateral deposited or withdrawn during the swap is not bought
+    let deposited: Uint128 =
+        read_collateral_stats(deps.storage, &pending_swap.ask_info)?.total_deposited;
+    let received: Uint128 = (balance + pending_swap.prev_deposited)
+        .saturating_sub(pending_swap.prev_balance + deposited);
+    if received < pending_swap.min_out {
+        return Err(ContractError::InsufficientSwapOutput {
+            min_out: pending_swap.min_out,
+            received,
+        });
+    }
+
+    Ok(received)
+}
+
+/// Repay every debt with the provided assets and return all the collateral,
+/// minus fees, to the owner; whatever exceeds the debt is refunded to the sender
+pub fn close_position(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    provided: Vec<Asset>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    for operation in [Operation::Burn, Operation::Withdraw] {
+        assert_not_globally_paused(deps.storage, operation)?;
+    }
+    let config: Config = read_config(deps.storage)?;
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    let position_owner = deps.api.addr_humanize(&position.owner)?;
+    let sender_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    for operation in [Operation::Burn, Operation::Withdraw] {
+        assert_position_sender(
+            deps.storage,
+            &position,
+            &sender_raw,
+            operation,
+            env.block.time.seconds(),
+        )?;
+        for debt in position.debt.iter() {
+            assert_not_paused(deps.storage, operation, &debt.info)?;
+        }
+    }
+
+    // a cw20 close carries a single token, so other debts have to be burned beforehand
+    for debt in position.debt.iter() {
+        let debt_info: AssetInfo = debt.info.to_normal(deps.api)?;
+        if !provided.iter().any(|a| a.info.equal(&debt_info)) {
+            return Err(ContractError::UnpaidDebt {
+                asset: debt_info.to_string(),
+            });
+        }
+    }
+
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+
+    // Burn fee is charged on the value of every repaid debt
+    let burn_fee: Vec<Asset> = if position.debt.is_empty() {
+        vec![]
+    } else {
+        let valuation: Valuation =
+            load_valuation(deps.as_ref(), &config, &position, true, Pricing::Mid)?;
+        let mut burn_fee_value: Uint128 = Uint128::zero();
+        for debt in position.debt.iter() {
+            let asset_config: AssetConfig = read_asset_config(deps.storage, &debt.info)?;
+            burn_fee_value += debt.amount
+                * asset_config.fee_schedule.burn_fee_rate
+                * find_debt_rate(&valuation, &debt.info)?.to_value;
+        }
+
+        to_normal_assets(
+            deps.api,
+            &take_collateral(&mut position.collateral, &valuation.collateral, burn_fee_value),
+        )?
+    };
+
+    // every debt has to be covered by the provided assets
+    let mut refund: Vec<Asset> = provided;
+    let mut burned: Vec<Asset> = vec![];
+    for debt in position.debt.iter() {
+        let debt_asset: Asset = Asset {
+            info: debt.info.to_normal(deps.api)?,
+            amount: debt.amount,
+        };
+        let provided_amount: Uint128 = refund
+            .iter()
+            .find(|a| a.info.equal(&debt_asset.info))
+            .map_or(Uint128::zero(), |a| a.amount);
+        if provided_amount < debt_asset.amount {
+            return Err(ContractError::InsufficientRepayment {
+                required: debt_asset.amount,
+                provided: provided_amount,
+            });
+        }
+
+        for a in refund.iter_mut().filter(|a| a.info.equal(&debt_asset.info)) {
+            a.amount -= debt_asset.amount;
+        }
+        burned.push(debt_asset);
+    }
+
+    let withdrawn: Vec<Asset> = to_normal_assets(deps.api, &position.collateral)?;
+    remove_position(deps.storage, position_idx)?;
+
+    let collector: Addr = deps.api.addr_humanize(&config.collector)?;
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = burned
+        .iter()
+        .map(burn_asset_msg)
+        .collect::<StdResult<Vec<CosmosMsg<SeiMsg>>>>()?;
+    messages.extend(transfer_msgs(&withdrawn, position_owner)?);
+    messages.extend(transfer_msgs(&stability_fee, collector.clone())?);
+    messages.extend(transfer_msgs(&burn_fee, collector)?);
+    messages.extend(transfer_msgs(&refund, sender)?);
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "close_position"),
+            attr("position_idx", position_idx.to_string()),
+            attr("burn_amount", assets_to_string(&burned)),
+            attr("withdraw_amount", assets_to_string(&withdrawn)),
+            attr("stability_fee", assets_to_string(&stability_fee)),
+            attr("burn_fee", assets_to_string(&burn_fee)),
+            attr("refund_amount", assets_to_string(&refund)),
+        ]))
+}
+
+pub fn transfer_position(
+    deps: DepsMut,
+    sender: Addr,
+    position_idx: Uint128,
+    recipient: String,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let position: Position = read_position(deps.storage, position_idx)?;
+    let position_owner = deps.api.addr_humanize(&position.owner)?;
+    if sender != position_owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // approvals given for this position do not carry over to the new owner
+    for approval in read_operator_approvals(deps.storage, &position.owner)? {
+        if approval.position_idx == Some(position_idx) {
+            remove_operator_approval(
+                deps.storage,
+                &position.owner,
+                &approval.operator,
+                approval.position_idx,
+            );
+        }
+    }
+
+    let recipient: Addr = deps.api.addr_validate(&recipient)?;
+    store_position_owner(
+        deps.storage,
+        position_idx,
+        &deps.api.addr_canonicalize(recipient.as_str())?,
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "transfer_position"),
+        attr("position_idx", position_idx.to_string()),
+        attr("from", position_owner),
+        attr("to", recipient),
+    ]))
+}
+
+pub fn approve_operator(
+    deps: DepsMut,
+    sender: Addr,
+    operator: String,
+    position_idx: Option<Uint128>,
+    operations: Option<Vec<Operation>>,
+    expires: Option<u64>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let owner_raw = deps.api.addr_canonicalize(sender.as_str())?;
+    if let Some(position_idx) = position_idx {
+        let position: Position = read_position(deps.storage, position_idx)?;
+        if position.owner != owner_raw {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+
+    let operator: Addr = deps.api.addr_validate(&operator)?;
+    store_operator_approval(
+        deps.storage,
+        &owner_raw,
+        &OperatorApproval {
+            operator: deps.api.addr_canonicalize(operator.as_str())?,
+            position_idx,
+            operations,
+            expires,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "approve_operator"),
+        attr("owner", sender),
+        attr("operator", operator),
+        attr(
+            "position_idx",
+            position_idx.map_or("all".to_string(), |idx| idx.to_string()),
+        ),
+    ]))
+}
+
+pub fn revoke_operator(
+    deps: DepsMut,
+    sender: Addr,
+    operator: String,
+    position_idx: Option<Uint128>,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let operator: Addr = deps.api.addr_validate(&operator)?;
+    remove_operator_approval(
+        deps.storage,
+        &deps.api.addr_canonicalize(sender.as_str())?,
+        &deps.api.addr_canonicalize(operator.as_str())?,
+        position_idx,
+    );
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "revoke_operator"),
+        attr("owner", sender),
+        attr("operator", operator),
+        attr(
+            "position_idx",
+            position_idx.map_or("all".to_string(), |idx| idx.to_string()),
+        ),
+    ]))
+}
+
+pub fn query_operator_approvals(deps: Deps, owner: String) -> StdResult<OperatorApprovalsResponse> {
+    let owner_raw = deps.api.addr_canonicalize(&owner)?;
+    let approvals: Vec<OperatorApprovalResponse> = read_operator_approvals(deps.storage, &owner_raw)?
+        .into_iter()
+        .map(|approval| {
+            Ok(OperatorApprovalResponse {
+                operator: deps.api.addr_humanize(&approval.operator)?.to_string(),
+                position_idx: approval.position_idx,
+                operations: approval.operations,
+                expires: approval.expires,
+            })
+        })
+        .collect::<StdResult<Vec<OperatorApprovalResponse>>>()?;
+
+    Ok(OperatorApprovalsResponse { owner, approvals })
+}
+
+pub fn query_position(deps: Deps, env: Env, position_idx: Uint128) -> StdResult<PositionResponse> {
+    let position: Position = read_position(deps.storage, position_idx)?;
+    position_response(deps, &env, position)
+}
+
+pub fn query_positions(
+    deps: Deps,
+    env: Env,
+    owner_addr: Option<String>,
+    asset_token: Option<String>,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<PositionsResponse> {
+    let positions: Vec<Position> = if let Some(owner_addr) = owner_addr {
+        read_positions_with_user_indexer(
+            deps.storage,
+            &deps.api.addr_canonicalize(&owner_addr)?,
+            start_after,
+            limit,
+            order_by,
+        )?
+    } else if let Some(asset_token) = asset_token {
+        read_positions_with_asset_indexer(
+            deps.storage,
+            &AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?,
+            start_after,
+            limit,
+            order_by,
+        )?
+    } else {
+        read_positions(deps.storage, start_after, limit, order_by)?
+    };
+
+    let positions = positions
+        .into_iter()
+        .map(|position| position_response(deps, &env, position))
+        .collect::<StdResult<Vec<PositionResponse>>>()?;
+
+    Ok(PositionsResponse { positions })
+}
+
+pub fn query_next_position_idx(deps: Deps) -> StdResult<NextPositionIdxResponse> {
+    let idx = read_position_idx(deps.storage)?;
+    let resp = NextPositionIdxResponse {
+        next_position_idx: idx,
+    };
+
+    Ok(resp)
+}
+
+fn position_response(deps: Deps, env: &Env, position: Position) -> StdResult<PositionResponse> {
+    let mut debt: Vec<Asset> = vec![];
+    let mut stability_fee: Vec<Asset> = vec![];
+    for position_debt in position.debt.iter() {
+        let asset_config: AssetConfig = read_asset_config(deps.storage, &position_debt.info)?;
+        let fee_index: Decimal = compute_fee_index(
+            deps.storage,
+            &position_debt.info,
+            asset_config.stability_fee_rate,
+            env.block.time.seconds(),
+        )?;
+
+        let asset_info: AssetInfo = position_debt.info.to_normal(deps.api)?;
+        debt.push(Asset {
+            info: asset_info.clone(),
+            amount: position_debt.amount,
+        });
+        stability_fee.push(Asset {
+            info: asset_info,
+            amount: compute_stability_fee(position_debt, fee_index),
+        });
+    }
+
+    let resp = PositionResponse {
+        idx: position.idx,
+        owner: deps.api.addr_humanize(&position.owner)?.to_string(),
+        collateral: to_normal_assets(deps.api, &position.collateral)?,
+        debt,
+        stability_fee,
+    };
+
+    Ok(resp)
+}
+
+pub fn burn(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    asset: Asset,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let burn_amount = asset.amount;
+    let asset_info_raw: AssetInfoRaw = asset.info.to_raw(deps.api)?;
+    let config: Config = read_config(deps.storage)?;
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    assert_not_paused(deps.storage, Operation::Burn, &asset_info_raw)?;
+
+    // Check the position has minted the asset
+    // also Check burn amount is non-zero
+    let debt_amount: Uint128 = assert_asset(&position, &asset_info_raw, burn_amount)?;
+
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
+    if debt_amount < burn_amount {
+        return Err(ContractError::InsufficientDebt {
+            requested: burn_amount,
+            available: debt_amount,
+        });
+    }
+
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = vec![];
+    let mut attributes: Vec<Attribute> = vec![];
+    let collector: Addr = deps.api.addr_humanize(&config.collector)?;
+
+    // settle the fee on the current debt before part of it is repaid
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+    messages.extend(transfer_msgs(&stability_fee, collector.clone())?);
+    attributes.push(attr("stability_fee", assets_to_string(&stability_fee)));
+
+    // fetch collateral info from collateral oracle;
+    // the end price is used for deprecated assets
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Mid)?;
+    let burn_value: Uint128 = burn_amount * find_debt_rate(&valuation, &asset_info_raw)?.to_value;
+
+    // Burn fee is charged on the burned value and taken from the collateral
+    let burn_fee_value: Uint128 = burn_value * asset_config.fee_schedule.burn_fee_rate;
+
+    // If the collateral is default denom asset and the asset is deprecated,
+    // anyone can execute burn the asset to any position without permission
+    if asset_config.end_price.is_some() {
+        // Burn deprecated asset to receive collaterals back,
+        // at most the burned debt's share of the basket
+        let (collateral_value, debt_value, _) = value_position(&position, &valuation);
+        let refund_value: Uint128 = std::cmp::min(
+            burn_value,
+            collateral_value.multiply_ratio(burn_value, debt_value),
+        );
+
+        // Subtract burn fee from refunded collateral
+        let burn_fee: Vec<Asset> = to_normal_assets(
+            deps.api,
+            &take_collateral(
+                &mut position.collateral,
+                &valuation.collateral,
+                std::cmp::min(burn_fee_value, refund_value),
+            ),
+        )?;
+        let refund_collateral: Vec<Asset> = to_normal_assets(
+            deps.api,
+            &take_collateral(
+                &mut position.collateral,
+                &valuation.collateral,
+                refund_value.saturating_sub(burn_fee_value),
+            ),
+        )?;
+
+        reduce_debt(&mut position, &asset_info_raw, burn_amount)?;
+
+        // due to rounding, include 1
+        if position
+            .collateral
+            .iter()
+            .all(|c| c.amount <= Uint128::from(1u128))
+            && position.debt.is_empty()
+        {
+            remove_position(deps.storage, position_idx)?;
+        } else {
+            store_position(deps.storage, position_idx, &position)?;
+        }
+
+        messages.extend(transfer_msgs(&burn_fee, collector)?);
+        attributes.push(attr("burn_fee", assets_to_string(&burn_fee)));
+
+        // Refund collateral msg
+        messages.extend(transfer_msgs(&refund_collateral, sender)?);
+        attributes.push(attr(
+            "refund_collateral_amount",
+            assets_to_string(&refund_collateral),
+        ));
+    } else {
+        assert_position_sender(
+            deps.storage,
+            &position,
+            &deps.api.addr_canonicalize(sender.as_str())?,
+            Operation::Burn,
+            env.block.time.seconds(),
+        )?;
+
+        // Subtract the burn fee from the position's collateral
+        let burn_fee: Vec<Asset> = to_normal_assets(
+            deps.api,
+            &take_collateral(&mut position.collateral, &valuation.collateral, burn_fee_value),
+        )?;
+        messages.extend(transfer_msgs(&burn_fee, collector)?);
+        attributes.push(attr("burn_fee", assets_to_string(&burn_fee)));
+
+        // Update asset amount
+        reduce_debt(&mut position, &asset_info_raw, burn_amount)?;
+        store_position(deps.storage, position_idx, &position)?;
+    }
+
+    Ok(Response::new()
+        .add_message(burn_asset_msg(&asset)?)
+        .add_messages(messages)
+        .add_attributes(vec![
+            attr("action", "burn"),
+            attr("position_idx", position_idx.to_string()),
+            attr("burn_amount", asset.to_string()),
+        ])
+        .add_attributes(attributes))
+}
+
+pub fn auction(
+    mut deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    position_idx: Uint128,
+    asset: Asset,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let asset_info_raw: AssetInfoRaw = asset.info.to_raw(deps.api)?;
+    let mut position: Position = read_position(deps.storage, position_idx)?;
+    assert_not_paused(deps.storage, Operation::Auction, &asset_info_raw)?;
+    let position_owner = deps.api.addr_humanize(&position.owner)?;
+
+    // Check the position has minted the asset
+    // also Check auction amount is non-zero
+    let debt_amount: Uint128 = assert_asset(&position, &asset_info_raw, asset.amount)?;
+
+    let asset_config: AssetConfig = read_asset_config(deps.storage, &asset_info_raw)?;
+    assert_market_open(&asset_config, env.block.time.seconds())?;
+    if asset.amount > debt_amount {
+        return Err(ContractError::InsufficientDebt {
+            requested: asset.amount,
+            available: debt_amount,
+        });
+    }
+
+    let mut messages: Vec<CosmosMsg<SeiMsg>> = vec![];
+    let collector: Addr = deps.api.addr_humanize(&config.collector)?;
+
+    let stability_fee: Vec<Asset> =
+        settle_stability_fee(deps.branch(), &env, &config, &mut position)?;
+    messages.extend(transfer_msgs(&stability_fee, collector.clone())?);
+
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), &config, &position, true, Pricing::Mid)?;
+    check_price_guards(deps.branch(), &env, &valuation)?;
+    record_revoked_collaterals(deps.storage, &valuation, env.block.time.seconds())?;
+    let asset_rate: &DebtRate = find_debt_rate(&valuation, &asset_info_raw)?;
+
+    // Compute collateral ratio across the whole position; holding a collateral
+    // revoked longer than the grace period raises the required ratio
+    let (collateral_value, debt_value, mut required_collateral_ratio) =
+        value_position(&position, &valuation);
+    if holds_overdue_revoked_collateral(
+        deps.storage,
+        &config,
+        &position,
+        env.block.time.seconds(),
+    )? {
+        required_collateral_ratio =
+            std::cmp::max(required_collateral_ratio, config.revoked_collateral_ratio);
+    }
+    let cur_collateral_ratio: Decimal = Decimal::from_ratio(collateral_value, debt_value);
+    if cur_collateral_ratio > required_collateral_ratio {
+        return Err(ContractError::SafelyCollateralized {
+            required: required_collateral_ratio,
+            actual: cur_collateral_ratio,
+        });
+    }
+
+    // the discount can not push the liquidator's share beyond the position's collateral
+    let auction_discount: Decimal = if cur_collateral_ratio > Decimal::one() {
+        decimal_min(
+            asset_config.auction_discount,
+            decimal_subtraction(
+                Decimal::one(),
+                reverse_decimal(cur_collateral_ratio),
+            ),
+        )
+    } else {
+        Decimal::zero()
+    };
+
+    // value of the collateral claimed by the liquidator
+    let discounted_price: Decimal =
+        reverse_decimal(decimal_subtraction(Decimal::one(), auction_discount));
+    let claimed_value: Uint128 = asset.amount * asset_rate.to_value * discounted_price;
+    let (liquidated_asset_amount, claimed_value) = if claimed_value > collateral_value {
+        // refunds asset token due to lack of collateral
+        (
+            collateral_value * reverse_decimal(discounted_price) * asset_rate.from_value,
+            collateral_value,
+        )
+    } else {
+        (asset.amount, claimed_value)
+    };
+
+    let refund_asset_amount: Uint128 = asset.amount.checked_sub(liquidated_asset_amount)?;
+    if !refund_asset_amount.is_zero() {
+        let refund_asset = Asset {
+            info: asset.info.clone(),
+            amount: refund_asset_amount,
+        };
+        messages.push(refund_asset.into_msg(sender.clone())?);
+    }
+
+    // Burn liquidated asset
+    messages.push(burn_asset_msg(&Asset {
+        info: asset.info.clone(),
+        amount: liquidated_asset_amount,
+    })?);
+
+    // Liquidation fee is charged on the liquidated value and taken from the claimed collateral
+    let liquidation_fee_value: Uint128 = liquidated_asset_amount
+        * asset_rate.to_value
+        * asset_config.fee_schedule.liquidation_fee_rate;
+    let liquidation_fee: Vec<Asset> = to_normal_assets(
+        deps.api,
+        &take_collateral(
+            &mut position.collateral,
+            &valuation.collateral,
+            liquidation_fee_value,
+        ),
+    )?;
+    let return_collateral: Vec<Asset> = to_normal_assets(
+        deps.api,
+        &take_collateral(
+            &mut position.collateral,
+            &valuation.collateral,
+            claimed_value.saturating_sub(liquidation_fee_value),
+        ),
+    )?;
+
+    // Update position
+    reduce_debt(&mut position, &asset_info_raw, liquidated_asset_amount)?;
+
+    if position.collateral.is_empty() && position.debt.is_empty() {
+        remove_position(deps.storage, position_idx)?;
+    } else {
+        store_position(deps.storage, position_idx, &position)?;
+    }
+
+    // Send collateral to the liquidator
+    messages.extend(transfer_msgs(&return_collateral, sender)?);
+    messages.extend(transfer_msgs(&liquidation_fee, collector)?);
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        attr("action", "auction"),
+        attr("position_idx", position_idx.to_string()),
+        attr("owner", position_owner.as_str()),
+        attr("return_collateral_amount", assets_to_string(&return_collateral)),
+        attr(
+            "liquidated_amount",
+            liquidated_asset_amount.to_string() + &asset.info.to_string(),
+        ),
+        attr("stability_fee", assets_to_string(&stability_fee)),
+        attr("liquidation_fee", assets_to_string(&liquidation_fee)),
+    ]))
+}
+
+/// Cumulative stability fee index of the asset at the given time.
+/// Interest accrues on the elapsed time since the stored checkpoint.
+pub fn compute_fee_index(
+    storage: &dyn Storage,
+    asset_info: &AssetInfoRaw,
+    stability_fee_rate: Decimal,
+    now: u64,
+) -> StdResult<Decimal> {
+    let fee_index: Decimal = match read_fee_index(storage, asset_info)? {
+        Some(fee_index) => {
+            let elapsed: u64 = now.saturating_sub(fee_index.last_updated);
+            let accrued_rate: Decimal = decimal_multiplication(
+                stability_fee_rate,
+                Decimal::from_ratio(elapsed, SECONDS_PER_YEAR),
+            );
+            fee_index.index + decimal_multiplication(fee_index.index, accrued_rate)
+        }
+        None => Decimal::one(),
+    };
+
+    Ok(fee_index)
+}
+
+/// Bring the stored fee index of the asset up to date and return it.
+/// The index is checkpointed at most once per block, so touching many
+/// positions in the same block does not compound the fee any further.
+pub fn accrue_fee_index(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfoRaw,
+    stability_fee_rate: Decimal,
+    now: u64,
+) -> StdResult<Decimal> {
+    if let Some(fee_index) = read_fee_index(storage, asset_info)? {
+        if fee_index.last_updated >= now {
+            return Ok(fee_index.index);
+        }
+    }
+
+    let index: Decimal = compute_fee_index(storage, asset_info, stability_fee_rate, now)?;
+    store_fee_index(
+        storage,
+        asset_info,
+        &FeeIndex {
+            index,
+            last_updated: now,
+        },
+    )?;
+
+    Ok(index)
+}
+
+/// stability fee owed on the debt since it was last settled, in asset units
+pub fn compute_stability_fee(debt: &Debt, fee_index: Decimal) -> Uint128 {
+    if debt.fee_index.is_zero() || fee_index <= debt.fee_index {
+        return Uint128::zero();
+    }
+
+    let accrued_debt: Uint128 = debt.amount * decimal_division(fee_index, debt.fee_index);
+    accrued_debt.saturating_sub(debt.amount)
+}
+
+/// Realize the stability fee accrued on every debt of the position.
+/// The fee is taken from the position's collateral basket and returned
+/// so the caller can send it to the collector.
+fn settle_stability_fee(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    position: &mut Position,
+) -> StdResult<Vec<Asset>> {
+    let mut fee_amounts: Vec<Uint128> = vec![];
+    for debt in position.debt.iter_mut() {
+        let asset_config: AssetConfig = read_asset_config(deps.storage, &debt.info)?;
+        let fee_index: Decimal = accrue_fee_index(
+            deps.storage,
+            &debt.info,
+            asset_config.stability_fee_rate,
+            env.block.time.seconds(),
+        )?;
+        fee_amounts.push(compute_stability_fee(debt, fee_index));
+        debt.fee_index = fee_index;
+    }
+
+    if fee_amounts.iter().all(|fee_amount| fee_amount.is_zero()) {
+        return Ok(vec![]);
+    }
+
+    // the fee is realized even on stale prices so deposits and burns are never blocked
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), config, position, false, Pricing::Mid)?;
+    let fee_value: Uint128 = valuation
+        .debt
+        .iter()
+        .zip(fee_amounts)
+        .map(|(rate, fee_amount)| fee_amount * rate.to_value)
+        .sum();
+
+    to_normal_assets(
+        deps.api,
+        &take_collateral(&mut position.collateral, &valuation.collateral, fee_value),
+    )
+}
+
+/// Side of the oracle confidence bands a position is valued at
+#[derive(Clone, Copy, PartialEq)]
+enum Pricing {
+    /// low end for collateral and high end for debt; used when the ratio is checked
+    /// after a user moves value out of or borrows against the position
+    Conservative,
+    Mid,
+}
+
+/// Prices of a position's collaterals and debts in base denom units
+struct Valuation {
+    collateral: Vec<CollateralRate>,
+    debt: Vec<DebtRate>,
+}
+
+struct CollateralRate {
+    info: AssetInfoRaw,
+    /// mid oracle price
+    price: Decimal,
+    /// base units worth one collateral unit
+    to_value: Decimal,
+    /// collateral units worth one base unit
+    from_value: Decimal,
+    /// oracle price band, which debts quoted in this collateral are priced through
+    price_band: PriceBand,
+    multiplier: Decimal,
+    is_revoked: bool,
+}
+
+struct DebtRate {
+    info: AssetInfoRaw,
+    /// mid oracle price
+    price: Decimal,
+    /// base units worth one asset unit
+    to_value: Decimal,
+    /// asset units worth one base unit
+    from_value: Decimal,
+    min_collateral_ratio: Decimal,
+}
+
+fn load_valuation(
+    deps: Deps,
+    config: &Config,
+    position: &Position,
+    check_expire: bool,
+    pricing: Pricing,
+) -> StdResult<Valuation> {
+    let oracle: Addr = deps.api.addr_humanize(&config.oracle)?;
+    let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+
+    let collateral: Vec<CollateralRate> = position
+        .collateral
+        .iter()
+        .map(|c| {
+            let collateral_info: CollateralInfoResponse =
+                load_collateral_asset_info(deps, collateral_oracle.clone(), &c.info)?;
+            let (price_band, multiplier, is_revoked) = load_collateral_price_band(
+                deps,
+                collateral_oracle.clone(),
+                &c.info,
+                &collateral_info,
+                check_expire,
+            )?;
+            let collateral_decimals: u8 = collateral_info.decimals;
+            let collateral_price: Decimal = match pricing {
+                Pricing::Conservative => price_band.low,
+                Pricing::Mid => price_band.mid,
+            };
+
+            Ok(CollateralRate {
+                info: c.info.clone(),
+                price: price_band.mid,
+                to_value: decimal_conversion_rate(
+                    collateral_price,
+                    collateral_decimals,
+                    Decimal::one(),
+                    config.base_decimals,
+                ),
+                from_value: decimal_conversion_rate(
+                    Decimal::one(),
+                    config.base_decimals,
+                    collateral_price,
+                    collateral_decimals,
+                ),
+                price_band,
+                multiplier,
+                is_revoked,
+            })
+        })
+        .collect::<StdResult<Vec<CollateralRate>>>()?;
+
+    // an asset quoted in the denom of a held collateral is priced through it
+    let collateral_bands: Vec<(AssetInfoRaw, PriceBand)> = collateral
+        .iter()
+        .map(|rate| (rate.info.clone(), rate.price_band))
+        .collect();
+
+    let debt: Vec<DebtRate> = position
+        .debt
+        .iter()
+        .map(|d| {
+            let asset_config: AssetConfig = read_asset_config(deps.storage, &d.info)?;
+            let price_band: PriceBand = load_asset_price_band_through(
+                deps,
+                oracle.clone(),
+                &d.info,
+                &collateral_bands,
+                check_expire,
+            )?;
+            let asset_price: Decimal = match pricing {
+                Pricing::Conservative => price_band.high,
+                Pricing::Mid => price_band.mid,
+            };
+
+            Ok(DebtRate {
+                info: d.info.clone(),
+                price: price_band.mid,
+                to_value: decimal_conversion_rate(
+                    asset_price,
+                    asset_config.decimals,
+                    Decimal::one(),
+                    config.base_decimals,
+                ),
+                from_value: decimal_conversion_rate(
+                    Decimal::one(),
+                    config.base_decimals,
+                    asset_price,
+                    asset_config.decimals,
+                ),
+                min_collateral_ratio: asset_config.min_collateral_ratio,
+            })
+        })
+        .collect::<StdResult<Vec<DebtRate>>>()?;
+
+    Ok(Valuation { collateral, debt })
+}
+
+/// Halt the operation on a tripped circuit breaker or on a price move over the
+/// threshold; prices that pass become the last seen ones
+fn check_price_guards(
+    mut deps: DepsMut,
+    env: &Env,
+    valuation: &Valuation,
+) -> Result<(), ContractError> {
+    let prices = valuation
+        .collateral
+        .iter()
+        .map(|rate| (&rate.info, rate.price))
+        .chain(valuation.debt.iter().map(|rate| (&rate.info, rate.price)));
+    for (asset_info, price) in prices {
+        check_price_guard(deps.branch(), env, asset_info, price)?;
+    }
+
+    Ok(())
+}
+
+/// Check the price against the guard of the asset, if any, and record it
+fn check_price_guard(
+    deps: DepsMut,
+    env: &Env,
+    asset_info: &AssetInfoRaw,
+    price: Decimal,
+) -> Result<(), ContractError> {
+    if let Some(mut price_guard) = read_price_guard(deps.storage, asset_info)? {
+        assert_price_guard(&price_guard, &asset_info.to_normal(deps.api)?, price)?;
+
+        price_guard.record(price, env.block.time.seconds());
+        store_price_guard(deps.storage, asset_info, &price_guard)?;
+    }
+
+    Ok(())
+}
+
+/// Start the grace period of a revoked collateral the first time a position
+/// holding it is valued, so auctions do not wait on `RegisterRevokedCollateral`
+fn record_revoked_collaterals(
+    storage: &mut dyn Storage,
+    valuation: &Valuation,
+    time: u64,
+) -> StdResult<()> {
+    for rate in valuation.collateral.iter().filter(|rate| rate.is_revoked) {
+        if read_revoked_collateral(storage, &rate.info)?.is_none() {
+            store_revoked_collateral(
+                storage,
+                &RevokedCollateral {
+                    info: rate.info.clone(),
+                    revoked_at: time,
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn find_debt_rate<'a>(
+    valuation: &'a Valuation,
+    asset_info: &AssetInfoRaw,
+) -> Result<&'a DebtRate, ContractError> {
+    valuation
+        .debt
+        .iter()
+        .find(|rate| rate.info.equal(asset_info))
+        .ok_or(ContractError::WrongAsset {})
+}
+
+/// Collateral and debt value of the position in base units, and the collateral ratio
+/// it requires: each debt's min_collateral_ratio and each collateral's multiplier
+/// are weighted by their value
+fn value_position(position: &Position, valuation: &Valuation) -> (Uint128, Uint128, Decimal) {
+    let mut collateral_value = Uint128::zero();
+    let mut weighted_collateral_value = Uint128::zero();
+    for c in position.collateral.iter() {
+        if let Some(rate) = valuation.collateral.iter().find(|rate| rate.info.equal(&c.info)) {
+            let value: Uint128 = c.amount * rate.to_value;
+            collateral_value += value;
+            weighted_collateral_value += value * rate.multiplier;
+        }
+    }
+
+    let mut debt_value = Uint128::zero();
+    let mut weighted_debt_value = Uint128::zero();
+    for d in position.debt.iter() {
+        if let Some(rate) = valuation.debt.iter().find(|rate| rate.info.equal(&d.info)) {
+            let value: Uint128 = d.amount * rate.to_value;
+            debt_value += value;
+            weighted_debt_value += value * rate.min_collateral_ratio;
+        }
+    }
+
+    if debt_value.is_zero() {
+        return (collateral_value, debt_value, Decimal::zero());
+    }
+
+    let multiplier: Decimal = if collateral_value.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(weighted_collateral_value, collateral_value)
+    };
+
+    (
+        collateral_value,
+        debt_value,
+        decimal_multiplication(
+            Decimal::from_ratio(weighted_debt_value, debt_value),
+            multiplier,
+        ),
+    )
+}
+
+fn holds_overdue_revoked_collateral(
+    storage: &dyn Storage,
+    config: &Config,
+    position: &Position,
+    time: u64,
+) -> StdResult<bool> {
+    for c in position.collateral.iter() {
+        if let Some(revoked_collateral) = read_revoked_collateral(storage, &c.info)? {
+            if time >= revoked_collateral.revoked_at + config.revoked_collateral_grace_period {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// The collateral ratio has to be within `SELF_LIQUIDATION_BUFFER` of the ratio
+/// the position is auctioned at; returns the current ratio
+fn assert_near_liquidation(
+    storage: &dyn Storage,
+    config: &Config,
+    position: &Position,
+    valuation: &Valuation,
+    time: u64,
+) -> Result<Decimal, ContractError> {
+    let (collateral_value, debt_value, mut required_collateral_ratio) =
+        value_position(position, valuation);
+    if holds_overdue_revoked_collateral(storage, config, position, time)? {
+        required_collateral_ratio =
+            std::cmp::max(required_collateral_ratio, config.revoked_collateral_ratio);
+    }
+
+    let required: Decimal = required_collateral_ratio * (Decimal::one() + SELF_LIQUIDATION_BUFFER);
+    let actual: Decimal = if debt_value.is_zero() {
+        Decimal::MAX
+    } else {
+        Decimal::from_ratio(collateral_value, debt_value)
+    };
+    if actual >= required {
+        return Err(ContractError::SafelyCollateralized { required, actual });
+    }
+
+    Ok(actual)
+}
+
+fn assert_collateral_ratio(position: &Position, valuation: &Valuation) -> Result<(), ContractError> {
+    let (collateral_value, debt_value, required) = value_position(position, valuation);
+    if debt_value.is_zero() {
+        return Ok(());
+    }
+
+    let actual = Decimal::from_ratio(collateral_value, debt_value);
+    if actual < required {
+        return Err(ContractError::LowCollateralRatio { required, actual });
+    }
+
+    Ok(())
+}
+
+/// Add the minted amount to the position's debt and take the mint fee from the collateral;
+/// the caller checks the collateral ratio against the returned valuation
+fn increase_debt(
+    mut deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    position: &mut Position,
+    asset_info: &AssetInfoRaw,
+    mint_amount: Uint128,
+) -> Result<(Valuation, Vec<Asset>), ContractError> {
+    // assert the asset migrated
+    let asset_config: AssetConfig = read_asset_config(deps.storage, asset_info)?;
+    assert_migrated_asset(&asset_config)?;
+    assert_market_open(&asset_config, env.block.time.seconds())?;
+
+    if position.find_debt(asset_info).is_none() {
+        // new debt starts accruing from the current fee index
+        let fee_index: Decimal = accrue_fee_index(
+            deps.storage,
+            asset_info,
+            asset_config.stability_fee_rate,
+            env.block.time.seconds(),
+        )?;
+        position.debt.push(Debt {
+            info: asset_info.clone(),
+            amount: Uint128::zero(),
+            fee_index,
+        });
+    }
+
+    // assert every collateral of the basket is listed and has not been migrated/revoked
+    let valuation: Valuation =
+        load_valuation(deps.as_ref(), config, position, true, Pricing::Conservative)?;
+    if valuation.collateral.iter().any(|rate| rate.is_revoked) {
+        return Err(ContractError::RevokedCollateral {});
+    }
+    check_price_guards(deps.branch(), env, &valuation)?;
+    record_revoked_collaterals(deps.storage, &valuation, env.block.time.seconds())?;
+
+    // Mint fee is charged on the minted value and taken from the collateral
+    let mint_fee_value: Uint128 = mint_amount
+        * asset_config.fee_schedule.mint_fee_rate
+        * find_debt_rate(&valuation, asset_info)?.to_value;
+    let mint_fee: Vec<Asset> = to_normal_assets(
+        deps.api,
+        &take_collateral(&mut position.collateral, &valuation.collateral, mint_fee_value),
+    )?;
+
+    // assert the debt ceiling of the asset
+    let asset_stats: AssetStats = read_asset_stats(deps.storage, asset_info)?;
+    assert_debt_ceiling(&asset_config, asset_stats.total_minted + mint_amount)?;
+
+    if let Some(debt) = position.find_debt_mut(asset_info) {
+        debt.amount += mint_amount;
+    }
+
+    Ok((valuation, mint_fee))
+}
+
+fn add_collateral(position: &mut Position, collateral_info: AssetInfoRaw, amount: Uint128) {
+    match position
+        .collateral
+        .iter_mut()
+        .find(|c| c.info.equal(&collateral_info))
+    {
+        Some(position_collateral) => position_collateral.amount += amount,
+        None => position.collateral.push(AssetRaw {
+            info: collateral_info,
+            amount,
+        }),
+    }
+}
+
+/// Decrease the collateral amount; emptied collaterals leave the basket
+fn subtract_collateral(
+    position: &mut Position,
+    collateral_info: &AssetInfoRaw,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let position_collateral = position
+        .collateral
+        .iter_mut()
+        .find(|c| c.info.equal(collateral_info))
+        .ok_or(ContractError::WrongCollateral {})?;
+
+    if position_collateral.amount < amount {
+        return Err(ContractError::InsufficientCollateral {
+            requested: amount,
+            available: position_collateral.amount,
+        });
+    }
+
+    position_collateral.amount -= amount;
+    position.collateral.retain(|c| !c.amount.is_zero());
+
+    Ok(())
+}
+
+/// Decrease the debt of the asset; repaid debts leave the position
+fn reduce_debt(
+    position: &mut Position,
+    asset_info: &AssetInfoRaw,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let debt: &mut Debt = position
+        .find_debt_mut(asset_info)
+        .ok_or(ContractError::WrongAsset {})?;
+    debt.amount = debt.amount.checked_sub(amount)?;
+    position.debt.retain(|d| !d.amount.is_zero());
+
+    Ok(())
+}
+
+/// Take collateral worth `value` base units out of the basket, in basket order.
+/// Takes the whole basket if it is worth less.
+fn take_collateral(
+    collateral: &mut Vec<AssetRaw>,
+    rates: &[CollateralRate],
+    value: Uint128,
+) -> Vec<AssetRaw> {
+    let mut remaining: Uint128 = value;
+    let mut taken: Vec<AssetRaw> = vec![];
+    for c in collateral.iter_mut() {
+        if remaining.is_zero() {
+            break;
+        }
+        let rate = match rates.iter().find(|rate| rate.info.equal(&c.info)) {
+            Some(rate) => rate,
+            None => continue,
+        };
+
+        let collateral_value: Uint128 = c.amount * rate.to_value;
+        let amount: Uint128 = if collateral_value <= remaining {
+            c.amount
+        } else {
+            std::cmp::min(remaining * rate.from_value, c.amount)
+        };
+        remaining = remaining.saturating_sub(collateral_value);
+
+        c.amount -= amount;
+        taken.push(AssetRaw {
+            info: c.info.clone(),
+            amount,
+        });
+    }
+    collateral.retain(|c| !c.amount.is_zero());
+
+    taken
+}
+
+fn to_normal_assets(api: &dyn Api, assets: &[AssetRaw]) -> StdResult<Vec<Asset>> {
+    assets.iter().map(|asset| asset.to_normal(api)).collect()
+}
+
+fn assets_to_string(assets: &[Asset]) -> String {
+    assets
+        .iter()
+        .map(|asset| asset.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Transfer every non-zero asset to the recipient
+pub fn transfer_msgs(assets: &[Asset], recipient: Addr) -> StdResult<Vec<CosmosMsg<SeiMsg>>> {
+    assets
+        .iter()
+        .filter(|asset| !asset.amount.is_zero())
+        .map(|asset| asset.clone().into_msg(recipient.clone()))
+        .collect()
+}
+
+/// Messages minting the asset to the recipient; token factory denoms are
+/// minted to the contract first and then sent on
+pub fn mint_asset_msgs(asset: &Asset, recipient: &Addr) -> StdResult<Vec<CosmosMsg<SeiMsg>>> {
+    match &asset.info {
+        AssetInfo::Token { contract_addr } => Ok(vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Mint {
+                recipient: recipient.to_string(),
+                amount: asset.amount,
+            })?,
+            funds: vec![],
+        })]),
+        AssetInfo::NativeToken { denom } => {
+            let amount = Coin {
+                denom: denom.to_string(),
+                amount: asset.amount,
+            };
+            Ok(vec![
+                CosmosMsg::Custom(SeiMsg::MintTokens {
+                    amount: amount.clone(),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![amount],
+                }),
+            ])
+        }
+    }
+}
+
+/// Message selling the offer asset held by the contract on the pair
+fn swap_msg(offer_asset: &Asset, pair: &Addr) -> StdResult<CosmosMsg<SeiMsg>> {
+    match &offer_asset.info {
+        AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Send {
+                contract: pair.to_string(),
+                amount: offer_asset.amount,
+                msg: to_json_binary(&PairCw20HookMsg::Swap {
+                    belief_price: None,
+                    max_spread: None,
+                    to: None,
+                })?,
+            })?,
+            funds: vec![],
+        })),
+        AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: pair.to_string(),
+            msg: to_json_binary(&PairExecuteMsg::Swap {
+                offer_asset: offer_asset.clone(),
+                belief_price: None,
+                max_spread: None,
+                to: None,
+            })?,
+            funds: vec![Coin {
+                denom: denom.to_string(),
+                amount: offer_asset.amount,
+            }],
+        })),
+    }
+}
+
+/// Message burning the asset held by the contract
+pub fn burn_asset_msg(asset: &Asset) -> StdResult<CosmosMsg<SeiMsg>> {
+    match &asset.info {
+        AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Burn {
+                amount: asset.amount,
+            })?,
+            funds: vec![],
+        })),
+        AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Custom(SeiMsg::BurnTokens {
+            amount: Coin {
+                denom: denom.to_string(),
+                amount: asset.amount,
+            },
+        })),
+    }
 }
@@ -0,0 +1,132 @@
+use cosmwasm_std::{attr, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult};
+
+use crate::{
+    asserts::assert_price_guard,
+    error::ContractError,
+    querier::load_guarded_price,
+    state::{
+        read_config, read_price_guard, remove_price_guard, store_price_guard, Config, PriceGuard,
+    },
+};
+use melange_protocol::{
+    asset::{AssetInfo, AssetInfoRaw},
+    mint::PriceGuardResponse,
+};
+use sei_cosmwasm::SeiMsg;
+
+/// Only the owner can set a circuit breaker; the current price
+/// becomes the last seen one
+pub fn update_price_guard(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset_token: String,
+    max_price_change: Option<Decimal>,
+    window: u64,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    if deps.api.addr_canonicalize(info.sender.as_str())? != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_info_raw: AssetInfoRaw =
+        AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+    match max_price_change {
+        Some(max_price_change) => {
+            if max_price_change.is_zero() {
+                return Err(ContractError::InvalidMaxPriceChange { max_price_change });
+            }
+
+            let price_guard = PriceGuard {
+                max_price_change,
+                window,
+                last_price: Some(load_guarded_price(deps.as_ref(), &asset_info_raw)?),
+                last_updated: env.block.time.seconds(),
+                tripped: false,
+            };
+            store_price_guard(deps.storage, &asset_info_raw, &price_guard)?;
+        }
+        None => remove_price_guard(deps.storage, &asset_info_raw),
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "update_price_guard"),
+        attr("asset_token", asset_token),
+    ]))
+}
+
+/// Anyone can check the price of a guarded asset; a move over the
+/// threshold trips the circuit breaker instead of failing
+pub fn check_price_move(
+    deps: DepsMut,
+    env: Env,
+    asset_token: String,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let asset_info: AssetInfo = AssetInfo::from_string(deps.api, &asset_token);
+    let asset_info_raw: AssetInfoRaw = asset_info.to_raw(deps.api)?;
+    let mut price_guard: PriceGuard = read_price_guard(deps.storage, &asset_info_raw)?
+        .ok_or_else(|| StdError::not_found("PriceGuard"))?;
+
+    let price: Decimal = load_guarded_price(deps.as_ref(), &asset_info_raw)?;
+    match assert_price_guard(&price_guard, &asset_info, price) {
+        Ok(()) => price_guard.record(price, env.block.time.seconds()),
+        Err(ContractError::PriceMoveExceeded { .. }) => price_guard.tripped = true,
+        Err(err) => return Err(err),
+    }
+    store_price_guard(deps.storage, &asset_info_raw, &price_guard)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "check_price_move"),
+        attr("asset_token", asset_token),
+        attr("price", price.to_string()),
+        attr("tripped", price_guard.tripped.to_string()),
+    ]))
+}
+
+/// The owner or guardian accepts the current price,
+/// lifting a tripped circuit breaker
+pub fn acknowledge_price_move(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset_token: String,
+) -> Result<Response<SeiMsg>, ContractError> {
+    let config: Config = read_config(deps.storage)?;
+    let sender_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_raw != config.owner && Some(sender_raw) != config.guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let asset_info_raw: AssetInfoRaw =
+        AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+    let mut price_guard: PriceGuard = read_price_guard(deps.storage, &asset_info_raw)?
+        .ok_or_else(|| StdError::not_found("PriceGuard"))?;
+
+    let price: Decimal = load_guarded_price(deps.as_ref(), &asset_info_raw)?;
+    price_guard.last_price = Some(price);
+    price_guard.last_updated = env.block.time.seconds();
+    price_guard.tripped = false;
+    store_price_guard(deps.storage, &asset_info_raw, &price_guard)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "acknowledge_price_move"),
+        attr("asset_token", asset_token),
+        attr("price", price.to_string()),
+    ]))
+}
+
+pub fn query_price_guard(deps: Deps, asset_token: String) -> StdResult<PriceGuardResponse> {
+    let asset_info_raw: AssetInfoRaw =
+        AssetInfo::from_string(deps.api, &asset_token).to_raw(deps.api)?;
+    let price_guard: PriceGuard = read_price_guard(deps.storage, &asset_info_raw)?
+        .ok_or_else(|| StdError::not_found("PriceGuard"))?;
+
+    Ok(PriceGuardResponse {
+        asset_token,
+        max_price_change: price_guard.max_price_change,
+        window: price_guard.window,
+        last_price: price_guard.last_price,
+        last_updated: price_guard.last_updated,
+        tripped: price_guard.tripped,
+    })
+}
@@ -1,68 +1,182 @@
 use cosmwasm_std::{
-    to_binary, Addr, Decimal, Deps, QuerierWrapper, QueryRequest, StdResult, WasmQuery,
+    to_json_binary, Addr, Decimal, Deps, QuerierWrapper, QueryRequest, StdError, StdResult,
+    WasmQuery,
 };
 
 use crate::{
-    math::decimal_division,
-    state::{read_config, read_fixed_price, Config},
-    asset::AssetInfoRaw
+    math::{decimal_division, decimal_multiplication, decimal_subtraction, MAX_DECIMALS},
+    state::{read_asset_config, read_config, read_fixed_price, Config},
 };
 use melange_protocol::collateral_oracle::{
     CollateralInfoResponse, CollateralPriceResponse, QueryMsg as CollateralOracleQueryMsg,
 };
-use melange_protocol::asset::AssetInfoRaw;
-use sei_cosmwasm::PriceResponse;
+use melange_protocol::asset::{AssetInfo, AssetInfoRaw};
+use melange_protocol::oracle::{PriceResponse, QueryMsg as OracleQueryMsg};
+use melange_protocol::pair::{PairInfo, QueryMsg as PairQueryMsg};
 
-const PRICE_EXPIRE_TIME: u64 = 60;
+/// default oldest price accepted, in seconds
+pub const PRICE_EXPIRE_TIME: u64 = 60;
+/// most oracle prices chained to quote an asset in another denom
+const MAX_PRICE_PATH_HOPS: usize = 3;
+
+/// Oracle price with its confidence band
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceBand {
+    pub low: Decimal,
+    pub mid: Decimal,
+    pub high: Decimal,
+}
+
+impl PriceBand {
+    /// fixed prices carry no uncertainty
+    pub fn exact(price: Decimal) -> Self {
+        PriceBand {
+            low: price,
+            mid: price,
+            high: price,
+        }
+    }
+
+    pub fn new(rate: Decimal, confidence: Decimal) -> StdResult<Self> {
+        if confidence >= rate {
+            return Err(StdError::generic_err(
+                "price confidence band is wider than the price",
+            ));
+        }
+
+        Ok(PriceBand {
+            low: decimal_subtraction(rate, confidence),
+            mid: rate,
+            high: rate + confidence,
+        })
+    }
+
+    /// band of the product, e.g. chaining a price in its quote's price
+    pub fn multiply(&self, other: &PriceBand) -> Self {
+        PriceBand {
+            low: decimal_multiplication(self.low, other.low),
+            mid: decimal_multiplication(self.mid, other.mid),
+            high: decimal_multiplication(self.high, other.high),
+        }
+    }
+
+    /// widest band of the cross rate
+    pub fn divide(&self, other: &PriceBand) -> Self {
+        PriceBand {
+            low: decimal_division(self.low, other.high),
+            mid: decimal_division(self.mid, other.mid),
+            high: decimal_division(self.high, other.low),
+        }
+    }
+}
 
 pub fn load_collateral_info(
     deps: Deps,
     collateral_oracle: Addr,
     collateral: &AssetInfoRaw,
+    collateral_info: &CollateralInfoResponse,
     check_expire: bool,
 ) -> StdResult<(Decimal, Decimal, bool)> {
+    let (price_band, collateral_multiplier, is_revoked) = load_collateral_price_band(
+        deps,
+        collateral_oracle,
+        collateral,
+        collateral_info,
+        check_expire,
+    )?;
+
+    Ok((price_band.mid, collateral_multiplier, is_revoked))
+}
+
+pub fn load_collateral_price_band(
+    deps: Deps,
+    collateral_oracle: Addr,
+    collateral: &AssetInfoRaw,
+    collateral_info: &CollateralInfoResponse,
+    check_expire: bool,
+) -> StdResult<(PriceBand, Decimal, bool)> {
     let config: Config = read_config(deps.storage)?;
     let collateral_denom: String = (collateral.to_normal(deps.api)?).to_string();
 
     // base collateral
     if collateral_denom == config.base_denom {
-        return Ok((Decimal::one(), Decimal::one(), false));
+        return Ok((PriceBand::exact(Decimal::one()), Decimal::one(), false));
     }
 
     // check if the collateral is a revoked mAsset
     let end_price = read_fixed_price(deps.storage, collateral);
 
     if let Some(end_price) = end_price {
-        // load collateral_multiplier from collateral oracle
         // if asset is revoked, no need to check for old price
-        let (collateral_multiplier, _) =
-            query_collateral_info(&deps.querier, collateral_oracle, collateral_denom)?;
-
-        Ok((end_price, collateral_multiplier, true))
+        Ok((PriceBand::exact(end_price), collateral_info.multiplier, true))
     } else {
-        // load collateral info from collateral oracle
-        let (collateral_oracle_price, collateral_multiplier, is_revoked) = query_collateral(
-            &deps.querier,
-            collateral_oracle,
-            collateral_denom,
-            check_expire,
-        )?;
+        // load collateral price from collateral oracle
+        let timeframe: Option<u64> = if check_expire {
+            Some(collateral_info.price_expire_time.unwrap_or(PRICE_EXPIRE_TIME))
+        } else {
+            None
+        };
+        query_collateral(&deps.querier, collateral_oracle, collateral_denom, timeframe)
+    }
+}
+
+/// Listing of the collateral in the collateral oracle, queried once and passed
+/// to the price, deposit cap and decimals consumers; the base collateral is
+/// always listed, uncapped and in base decimals
+pub fn load_collateral_asset_info(
+    deps: Deps,
+    collateral_oracle: Addr,
+    collateral: &AssetInfoRaw,
+) -> StdResult<CollateralInfoResponse> {
+    let config: Config = read_config(deps.storage)?;
+    let collateral_denom: String = (collateral.to_normal(deps.api)?).to_string();
 
-        Ok((collateral_oracle_price, collateral_multiplier, is_revoked))
+    if collateral_denom == config.base_denom {
+        return Ok(CollateralInfoResponse {
+            asset: collateral_denom,
+            multiplier: Decimal::one(),
+            source_type: "base".to_string(),
+            is_revoked: false,
+            max_deposit: None,
+            decimals: config.base_decimals,
+            price_expire_time: None,
+        });
     }
+
+    query_collateral_asset_info(&deps.querier, collateral_oracle, collateral_denom)
 }
 
-// queries only collateral information (multiplier and is_revoked), without price
-pub fn query_collateral_info(
+// queries the collateral listing from the collateral oracle, rejecting decimals
+// the conversion rates can not handle
+pub fn query_collateral_asset_info(
     querier: &QuerierWrapper,
     collateral_oracle: Addr,
     asset: String,
-) -> StdResult<(Decimal, bool)> {
+) -> StdResult<CollateralInfoResponse> {
     let res: CollateralInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: collateral_oracle.to_string(),
-        msg: to_binary(&CollateralOracleQueryMsg::CollateralAssetInfo { asset })?,
+        msg: to_json_binary(&CollateralOracleQueryMsg::CollateralAssetInfo { asset })?,
     }))?;
 
+    if res.decimals > MAX_DECIMALS {
+        return Err(StdError::generic_err(format!(
+            "collateral decimals must be at most {}; got {}",
+            MAX_DECIMALS, res.decimals
+        )));
+    }
+
+    Ok(res)
+}
+
+// queries only collateral information (multiplier and is_revoked), without price
+pub fn query_collateral_info(
+    querier: &QuerierWrapper,
+    collateral_oracle: Addr,
+    asset: String,
+) -> StdResult<(Decimal, bool)> {
+    let res: CollateralInfoResponse =
+        query_collateral_asset_info(querier, collateral_oracle, asset)?;
+
     Ok((res.multiplier, res.is_revoked))
 }
 
@@ -71,19 +185,42 @@ pub fn query_collateral(
     querier: &QuerierWrapper,
     collateral_oracle: Addr,
     asset: String,
-    check_expire: bool,
-) -> StdResult<(Decimal, Decimal, bool)> {
-    let timeframe: Option<u64> = if check_expire {
-        Some(PRICE_EXPIRE_TIME)
-    } else {
-        None
-    };
+    timeframe: Option<u64>,
+) -> StdResult<(PriceBand, Decimal, bool)> {
     let res: CollateralPriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: collateral_oracle.to_string(),
-        msg: to_binary(&CollateralOracleQueryMsg::CollateralPrice { asset, timeframe })?,
+        msg: to_json_binary(&CollateralOracleQueryMsg::CollateralPrice { asset, timeframe })?,
     }))?;
 
-    Ok((res.rate, res.multiplier, res.is_revoked))
+    Ok((
+        PriceBand::new(res.rate, res.confidence)?,
+        res.multiplier,
+        res.is_revoked,
+    ))
+}
+
+// loads the asset the pair returns for the offer asset
+pub fn load_pair_ask_info(
+    querier: &QuerierWrapper,
+    pair: Addr,
+    offer_info: &AssetInfo,
+) -> StdResult<AssetInfo> {
+    let res: PairInfo = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair.to_string(),
+        msg: to_json_binary(&PairQueryMsg::Pair {})?,
+    }))?;
+
+    let [first, second] = res.asset_infos;
+    if first.equal(offer_info) {
+        Ok(second)
+    } else if second.equal(offer_info) {
+        Ok(first)
+    } else {
+        Err(StdError::generic_err(format!(
+            "pair does not trade {}",
+            offer_info
+        )))
+    }
 }
 
 pub fn load_asset_price(
@@ -92,60 +229,205 @@ pub fn load_asset_price(
     asset: &AssetInfoRaw,
     check_expire: bool,
 ) -> StdResult<Decimal> {
+    Ok(load_asset_price_band(deps, oracle, asset, check_expire)?.mid)
+}
+
+pub fn load_asset_price_band(
+    deps: Deps,
+    oracle: Addr,
+    asset: &AssetInfoRaw,
+    check_expire: bool,
+) -> StdResult<PriceBand> {
     let config: Config = read_config(deps.storage)?;
 
     // check if the asset has a stored end_price or pre_ipo_price
     let stored_price = read_fixed_price(deps.storage, asset);
 
-    let price: Decimal = if let Some(stored_price) = stored_price {
-        stored_price
+    let price_band: PriceBand = if let Some(stored_price) = stored_price {
+        PriceBand::exact(stored_price)
     } else {
         let asset_denom: String = (asset.to_normal(deps.api)?).to_string();
         if asset_denom == config.base_denom {
-            Decimal::one()
+            PriceBand::exact(Decimal::one())
         } else {
             // fetch price from oracle
-            query_price(&deps.querier, oracle, asset_denom, None, check_expire)?
+            query_price(
+                &deps.querier,
+                oracle,
+                &config.base_denom,
+                asset_denom,
+                None,
+                asset_price_timeframe(deps, asset, check_expire),
+            )?
         }
     };
 
-    Ok(price)
+    Ok(price_band)
+}
+
+/// Price of the asset in base denom; when its oracle path reaches the denom of
+/// one of the given collaterals, the rest of the path is priced by that
+/// collateral's band instead of crossing through base denom
+pub fn load_asset_price_band_through(
+    deps: Deps,
+    oracle: Addr,
+    asset: &AssetInfoRaw,
+    collaterals: &[(AssetInfoRaw, PriceBand)],
+    check_expire: bool,
+) -> StdResult<PriceBand> {
+    let config: Config = read_config(deps.storage)?;
+    let asset_denom: String = (asset.to_normal(deps.api)?).to_string();
+    if asset_denom == config.base_denom || read_fixed_price(deps.storage, asset).is_some() {
+        return load_asset_price_band(deps, oracle, asset, check_expire);
+    }
+
+    let collateral_denoms: Vec<String> = collaterals
+        .iter()
+        .map(|(info, _)| Ok((info.to_normal(deps.api)?).to_string()))
+        .collect::<StdResult<Vec<String>>>()?;
+    let quote_assets: Vec<&str> = collateral_denoms.iter().map(|d| d.as_str()).collect();
+    let (price_band, denom) = query_price_path(
+        &deps.querier,
+        &oracle,
+        &config.base_denom,
+        asset_denom,
+        &quote_assets,
+        asset_price_timeframe(deps, asset, check_expire),
+    )?;
+
+    match collateral_denoms.iter().position(|d| *d == denom) {
+        Some(i) => Ok(price_band.multiply(&collaterals[i].1)),
+        None => Ok(price_band),
+    }
 }
 
-// fixme: check PriceRespone from sei if it works correct
+/// Price of the asset in the collateral's denom, if the oracle quotes the asset
+/// along a path reaching that denom without crossing through base denom
+pub fn load_asset_price_in_collateral(
+    deps: Deps,
+    oracle: Addr,
+    asset: &AssetInfoRaw,
+    collateral: &AssetInfoRaw,
+    check_expire: bool,
+) -> StdResult<Option<PriceBand>> {
+    let config: Config = read_config(deps.storage)?;
+    let collateral_denom: String = (collateral.to_normal(deps.api)?).to_string();
+    if collateral_denom == config.base_denom || read_fixed_price(deps.storage, asset).is_some() {
+        return Ok(None);
+    }
+
+    let (price_band, quote_denom) = query_price_path(
+        &deps.querier,
+        &oracle,
+        &config.base_denom,
+        (asset.to_normal(deps.api)?).to_string(),
+        &[&collateral_denom],
+        asset_price_timeframe(deps, asset, check_expire),
+    )?;
+
+    Ok(if quote_denom == collateral_denom {
+        Some(price_band)
+    } else {
+        None
+    })
+}
+
+// oracle prices can be no older than the expire time of the asset
+fn asset_price_timeframe(deps: Deps, asset: &AssetInfoRaw, check_expire: bool) -> Option<u64> {
+    if check_expire {
+        let price_expire_time: Option<u64> = read_asset_config(deps.storage, asset)
+            .ok()
+            .and_then(|asset_config| asset_config.price_expire_time);
+        Some(price_expire_time.unwrap_or(PRICE_EXPIRE_TIME))
+    } else {
+        None
+    }
+}
+
+/// Price of a guarded asset, read from the oracle for registered assets
+/// and from the collateral oracle otherwise
+pub fn load_guarded_price(deps: Deps, asset: &AssetInfoRaw) -> StdResult<Decimal> {
+    let config: Config = read_config(deps.storage)?;
+    if read_asset_config(deps.storage, asset).is_ok() {
+        load_asset_price(deps, deps.api.addr_humanize(&config.oracle)?, asset, true)
+    } else {
+        let collateral_oracle: Addr = deps.api.addr_humanize(&config.collateral_oracle)?;
+        let collateral_info: CollateralInfoResponse =
+            load_collateral_asset_info(deps, collateral_oracle.clone(), asset)?;
+        let (price, _, _) =
+            load_collateral_info(deps, collateral_oracle, asset, &collateral_info, true)?;
+        Ok(price)
+    }
+}
+
+/// Price of the asset in the quote asset, base denom if not given; the quote asset
+/// is crossed through base denom unless it is on the price path of the asset
 pub fn query_price(
     querier: &QuerierWrapper,
     oracle: Addr,
+    base_denom: &str,
     base_asset: String,
     quote_asset: Option<String>,
-    check_expire: bool,
-) -> StdResult<Decimal> {
-    let timeframe: Option<u64> = if check_expire {
-        Some(PRICE_EXPIRE_TIME)
-    } else {
-        None
-    };
-    let base_res: PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-        contract_addr: oracle.to_string(),
-        msg: to_binary(&OracleQueryMsg::Price {
-            asset_token: base_asset,
-            timeframe,
-        })?,
-    }))?;
+    timeframe: Option<u64>,
+) -> StdResult<PriceBand> {
+    let quote_asset: String = quote_asset.unwrap_or_else(|| base_denom.to_string());
+    let (base_band, denom) = query_price_path(
+        querier,
+        &oracle,
+        base_denom,
+        base_asset,
+        &[&quote_asset],
+        timeframe,
+    )?;
+    if denom == quote_asset {
+        return Ok(base_band);
+    }
+
+    let (quote_band, _) = query_price_path(
+        querier,
+        &oracle,
+        base_denom,
+        quote_asset,
+        &[],
+        timeframe,
+    )?;
+
+    Ok(base_band.divide(&quote_band))
+}
 
-    let rate = if let Some(quote_asset) = quote_asset {
-        let quote_res: PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+/// Chain the oracle prices of the asset through their quote denoms until reaching
+/// one of the quote assets or base denom; returns the price in the denom reached
+pub fn query_price_path(
+    querier: &QuerierWrapper,
+    oracle: &Addr,
+    base_denom: &str,
+    asset: String,
+    quote_assets: &[&str],
+    timeframe: Option<u64>,
+) -> StdResult<(PriceBand, String)> {
+    let mut price_band = PriceBand::exact(Decimal::one());
+    let mut denom: String = asset;
+    let mut hops: usize = 0;
+    while !quote_assets.contains(&denom.as_str()) && denom != base_denom {
+        if hops == MAX_PRICE_PATH_HOPS {
+            return Err(StdError::generic_err(format!(
+                "price path does not reach {} within {} hops",
+                base_denom, MAX_PRICE_PATH_HOPS
+            )));
+        }
+
+        let res: PriceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
             contract_addr: oracle.to_string(),
-            msg: to_binary(&OracleQueryMsg::Price {
-                asset_token: quote_asset,
+            msg: to_json_binary(&OracleQueryMsg::Price {
+                asset_token: denom,
                 timeframe,
             })?,
         }))?;
 
-        decimal_division(base_res.rate, quote_res.rate)
-    } else {
-        base_res.rate
-    };
+        price_band = price_band.multiply(&PriceBand::new(res.rate, res.confidence)?);
+        denom = res.quote_denom.unwrap_or_else(|| base_denom.to_string());
+        hops += 1;
+    }
 
-    Ok(rate)
+    Ok((price_band, denom))
 }
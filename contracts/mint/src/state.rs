@@ -1,19 +1,37 @@
+// buckets from cosmwasm-storage until the state moves to cw-storage-plus
+#![allow(deprecated)]
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Decimal, StdError, StdResult, Storage, Uint128};
+use cosmwasm_std::{CanonicalAddr, Decimal, Order, StdError, StdResult, Storage, Uint128};
 
 use cosmwasm_storage::{singleton, singleton_read, Bucket, ReadonlyBucket};
 use melange_protocol::common::OrderBy;
+use melange_protocol::mint::{FeeSchedule, MarketHours, Operation, PauseInfo, TimelockedChange};
 use melange_protocol::asset::{AssetInfoRaw, AssetRaw};
 use std::convert::TryInto;
 
 pub static PREFIX_ASSET_CONFIG: &[u8] = b"asset_config";
-static PREFIX_POSITION: &[u8] = b"position";
+pub static PREFIX_POSITION: &[u8] = b"position";
 static PREFIX_INDEX_BY_USER: &[u8] = b"by_user";
 static PREFIX_INDEX_BY_ASSET: &[u8] = b"by_asset";
+static PREFIX_INDEX_BY_COLLATERAL: &[u8] = b"by_collateral";
+static PREFIX_ASSET_STATS: &[u8] = b"asset_stats";
+static PREFIX_COLLATERAL_STATS: &[u8] = b"collateral_stats";
+static PREFIX_FEE_INDEX: &[u8] = b"fee_index";
 pub static KEY_CONFIG: &[u8] = b"config";
+static KEY_PAUSE: &[u8] = b"pause";
+static KEY_PENDING_OWNER: &[u8] = b"pending_owner";
+static KEY_CHANGE_IDX: &[u8] = b"change_idx";
+static PREFIX_QUEUED_CHANGE: &[u8] = b"queued_change";
+static PREFIX_ASSET_PAUSE: &[u8] = b"asset_pause";
 static KEY_POSITION_IDX: &[u8] = b"position_idx";
+static PREFIX_OPERATOR_APPROVAL: &[u8] = b"operator_approval";
+static KEY_PENDING_SWAP: &[u8] = b"pending_swap";
+static KEY_PENDING_FLASH_MINT: &[u8] = b"pending_flash_mint";
+static PREFIX_REVOKED_COLLATERAL: &[u8] = b"revoked_collateral";
+static PREFIX_PRICE_GUARD: &[u8] = b"price_guard";
 
 pub fn store_position_idx(storage: &mut dyn Storage, position_idx: Uint128) -> StdResult<()> {
     singleton(storage, KEY_POSITION_IDX).save(&position_idx)
@@ -33,8 +51,17 @@ pub struct Config {
     pub melange_factory: CanonicalAddr,
     pub lock: CanonicalAddr,
     pub base_denom: String,
+    pub base_decimals: u8,
     pub token_code_id: u64,
     pub protocol_fee_rate: Decimal,
+    /// can pause operations but not lift a pause
+    pub guardian: Option<CanonicalAddr>,
+    /// delay in seconds before a queued change can be executed
+    pub timelock_period: u64,
+    /// seconds positions on a revoked collateral have to move to another collateral
+    pub revoked_collateral_grace_period: u64,
+    /// required collateral ratio of positions on a revoked collateral after the grace period
+    pub revoked_collateral_ratio: Decimal,
 }
 
 pub fn store_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()> {
@@ -45,44 +72,279 @@ pub fn read_config(storage: &dyn Storage) -> StdResult<Config> {
     singleton_read(storage, KEY_CONFIG).load()
 }
 
+pub fn store_pending_owner(storage: &mut dyn Storage, owner: &CanonicalAddr) -> StdResult<()> {
+    singleton(storage, KEY_PENDING_OWNER).save(owner)
+}
+
+pub fn read_pending_owner(storage: &dyn Storage) -> StdResult<Option<CanonicalAddr>> {
+    singleton_read(storage, KEY_PENDING_OWNER).may_load()
+}
+
+pub fn remove_pending_owner(storage: &mut dyn Storage) {
+    singleton::<CanonicalAddr>(storage, KEY_PENDING_OWNER).remove()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueuedChange {
+    pub id: u64,
+    pub change: TimelockedChange,
+    pub executable_at: u64,
+}
+
+/// returns the id for the next queued change and increments it
+pub fn next_change_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let id: u64 = singleton_read(storage, KEY_CHANGE_IDX)
+        .may_load()?
+        .unwrap_or(1u64);
+    singleton(storage, KEY_CHANGE_IDX).save(&(id + 1))?;
+    Ok(id)
+}
+
+pub fn store_queued_change(storage: &mut dyn Storage, change: &QueuedChange) -> StdResult<()> {
+    let mut change_bucket: Bucket<QueuedChange> = Bucket::new(storage, PREFIX_QUEUED_CHANGE);
+    change_bucket.save(&change.id.to_be_bytes(), change)
+}
+
+pub fn read_queued_change(storage: &dyn Storage, id: u64) -> StdResult<QueuedChange> {
+    let change_bucket: ReadonlyBucket<QueuedChange> =
+        ReadonlyBucket::new(storage, PREFIX_QUEUED_CHANGE);
+    match change_bucket.load(&id.to_be_bytes()) {
+        Ok(change) => Ok(change),
+        _ => Err(StdError::generic_err("no queued change found")),
+    }
+}
+
+pub fn remove_queued_change(storage: &mut dyn Storage, id: u64) {
+    let mut change_bucket: Bucket<QueuedChange> = Bucket::new(storage, PREFIX_QUEUED_CHANGE);
+    change_bucket.remove(&id.to_be_bytes())
+}
+
+pub fn read_queued_changes(
+    storage: &dyn Storage,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<QueuedChange>> {
+    let change_bucket: ReadonlyBucket<QueuedChange> =
+        ReadonlyBucket::new(storage, PREFIX_QUEUED_CHANGE);
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| {
+        let mut v = id.to_be_bytes().to_vec();
+        v.push(1);
+        v
+    });
+
+    change_bucket
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+pub fn store_global_pause(storage: &mut dyn Storage, pause_info: &PauseInfo) -> StdResult<()> {
+    singleton(storage, KEY_PAUSE).save(pause_info)
+}
+
+pub fn read_global_pause(storage: &dyn Storage) -> StdResult<PauseInfo> {
+    Ok(singleton_read(storage, KEY_PAUSE)
+        .may_load()?
+        .unwrap_or_default())
+}
+
+pub fn store_asset_pause(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfoRaw,
+    pause_info: &PauseInfo,
+) -> StdResult<()> {
+    let mut pause_bucket: Bucket<PauseInfo> = Bucket::new(storage, PREFIX_ASSET_PAUSE);
+    pause_bucket.save(asset_info.as_bytes(), pause_info)
+}
+
+pub fn read_asset_pause(storage: &dyn Storage, asset_info: &AssetInfoRaw) -> StdResult<PauseInfo> {
+    let pause_bucket: ReadonlyBucket<PauseInfo> =
+        ReadonlyBucket::new(storage, PREFIX_ASSET_PAUSE);
+    Ok(pause_bucket
+        .may_load(asset_info.as_bytes())?
+        .unwrap_or_default())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RevokedCollateral {
+    pub info: AssetInfoRaw,
+    /// block time in seconds the revocation was registered
+    pub revoked_at: u64,
+}
+
+pub fn store_revoked_collateral(
+    storage: &mut dyn Storage,
+    revoked_collateral: &RevokedCollateral,
+) -> StdResult<()> {
+    let mut revoked_bucket: Bucket<RevokedCollateral> =
+        Bucket::new(storage, PREFIX_REVOKED_COLLATERAL);
+    revoked_bucket.save(revoked_collateral.info.as_bytes(), revoked_collateral)
+}
+
+pub fn read_revoked_collateral(
+    storage: &dyn Storage,
+    collateral_info: &AssetInfoRaw,
+) -> StdResult<Option<RevokedCollateral>> {
+    let revoked_bucket: ReadonlyBucket<RevokedCollateral> =
+        ReadonlyBucket::new(storage, PREFIX_REVOKED_COLLATERAL);
+    revoked_bucket.may_load(collateral_info.as_bytes())
+}
+
+pub fn read_revoked_collaterals(storage: &dyn Storage) -> StdResult<Vec<RevokedCollateral>> {
+    let revoked_bucket: ReadonlyBucket<RevokedCollateral> =
+        ReadonlyBucket::new(storage, PREFIX_REVOKED_COLLATERAL);
+
+    revoked_bucket
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+/// Circuit breaker on the price of an asset or collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceGuard {
+    /// largest relative price move accepted within `window`
+    pub max_price_change: Decimal,
+    /// seconds a recorded price stays the one moves are measured from
+    pub window: u64,
+    pub last_price: Option<Decimal>,
+    pub last_updated: u64,
+    /// halts guarded operations until the owner or guardian acknowledges the move
+    pub tripped: bool,
+}
+
+impl PriceGuard {
+    /// Record a price within the threshold; the recorded price is only replaced
+    /// once the window has passed, so small moves can not add up past the threshold
+    pub fn record(&mut self, price: Decimal, time: u64) {
+        if self.last_price.is_none() || time >= self.last_updated + self.window {
+            self.last_price = Some(price);
+            self.last_updated = time;
+        }
+    }
+}
+
+pub fn store_price_guard(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfoRaw,
+    price_guard: &PriceGuard,
+) -> StdResult<()> {
+    let mut guard_bucket: Bucket<PriceGuard> = Bucket::new(storage, PREFIX_PRICE_GUARD);
+    guard_bucket.save(asset_info.as_bytes(), price_guard)
+}
+
+pub fn read_price_guard(
+    storage: &dyn Storage,
+    asset_info: &AssetInfoRaw,
+) -> StdResult<Option<PriceGuard>> {
+    let guard_bucket: ReadonlyBucket<PriceGuard> =
+        ReadonlyBucket::new(storage, PREFIX_PRICE_GUARD);
+    guard_bucket.may_load(asset_info.as_bytes())
+}
+
+pub fn remove_price_guard(storage: &mut dyn Storage, asset_info: &AssetInfoRaw) {
+    let mut guard_bucket: Bucket<PriceGuard> = Bucket::new(storage, PREFIX_PRICE_GUARD);
+    guard_bucket.remove(asset_info.as_bytes())
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AssetConfig {
-    pub token: CanonicalAddr,
+    /// cw20 token or token factory denom of the asset
+    pub info: AssetInfoRaw,
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    pub auction_discount: Decimal,
     pub min_collateral_ratio: Decimal,
     pub end_price: Option<Decimal>,
+    /// annual stability fee charged on the minted debt; none if not set
+    #[serde(default)]
+    pub stability_fee_rate: Decimal,
+    /// no fees if not set
+    #[serde(default)]
+    pub fee_schedule: FeeSchedule,
+    /// maximum total amount that can be minted
+    #[serde(default)]
+    pub debt_ceiling: Option<Uint128>,
+    /// oldest oracle price accepted; the querier default if not set
+    #[serde(default)]
+    pub price_expire_time: Option<u64>,
+    /// always open if not set
+    #[serde(default)]
+    pub market_hours: Option<MarketHours>,
+}
+
+// cw20 and token factory assets use 6 decimals unless registered otherwise
+fn default_decimals() -> u8 {
+    6u8
+}
+
+pub fn store_asset_config(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfoRaw,
+    asset: &AssetConfig,
+) -> StdResult<()> {
+    let mut asset_bucket: Bucket<AssetConfig> = Bucket::new(storage, PREFIX_ASSET_CONFIG);
+    asset_bucket.save(asset_info.as_bytes(), asset)
 }
 
 // check if the asset has either end_price or pre_ipo_price
 pub fn read_fixed_price(storage: &dyn Storage, asset_info: &AssetInfoRaw) -> Option<Decimal> {
-    match asset_info {
-        AssetInfoRaw::Token { contract_addr } => {
-            let asset_bucket: ReadonlyBucket<AssetConfig> =
-                ReadonlyBucket::new(storage, PREFIX_ASSET_CONFIG);
-            let res = asset_bucket.load(contract_addr.as_slice());
-            match res {
-                Ok(data) => {
-                    data.end_price
-                }
-                _ => None,
-            }
-        }
+    let asset_bucket: ReadonlyBucket<AssetConfig> =
+        ReadonlyBucket::new(storage, PREFIX_ASSET_CONFIG);
+    let res = asset_bucket.load(asset_info.as_bytes());
+    match res {
+        Ok(data) => data.end_price,
         _ => None,
     }
 }
 
 pub fn read_asset_config(
     storage: &dyn Storage,
-    asset_token: &CanonicalAddr,
+    asset_info: &AssetInfoRaw,
 ) -> StdResult<AssetConfig> {
     let asset_bucket: ReadonlyBucket<AssetConfig> =
         ReadonlyBucket::new(storage, PREFIX_ASSET_CONFIG);
-    let res = asset_bucket.load(asset_token.as_slice());
+    let res = asset_bucket.load(asset_info.as_bytes());
     match res {
         Ok(data) => Ok(data),
         _ => Err(StdError::generic_err("no asset data stored")),
     }
 }
 
+/// cumulative stability fee index of an asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeIndex {
+    pub index: Decimal,
+    pub last_updated: u64,
+}
+
+pub fn store_fee_index(
+    storage: &mut dyn Storage,
+    asset_info: &AssetInfoRaw,
+    fee_index: &FeeIndex,
+) -> StdResult<()> {
+    let mut fee_index_bucket: Bucket<FeeIndex> = Bucket::new(storage, PREFIX_FEE_INDEX);
+    fee_index_bucket.save(asset_info.as_bytes(), fee_index)
+}
+
+pub fn read_fee_index(
+    storage: &dyn Storage,
+    asset_info: &AssetInfoRaw,
+) -> StdResult<Option<FeeIndex>> {
+    let fee_index_bucket: ReadonlyBucket<FeeIndex> =
+        ReadonlyBucket::new(storage, PREFIX_FEE_INDEX);
+    fee_index_bucket.may_load(asset_info.as_bytes())
+}
+
 /// create position with index
 pub fn create_position(
     storage: &mut dyn Storage,
@@ -96,11 +358,8 @@ pub fn create_position(
         Bucket::multilevel(storage, &[PREFIX_INDEX_BY_USER, position.owner.as_slice()]);
     position_indexer_by_user.save(&idx.u128().to_be_bytes(), &true)?;
 
-    let mut position_indexer_by_asset: Bucket<bool> = Bucket::multilevel(
-        storage,
-        &[PREFIX_INDEX_BY_ASSET, position.asset.info.as_bytes()],
-    );
-    position_indexer_by_asset.save(&idx.u128().to_be_bytes(), &true)?;
+    update_asset_indexer(storage, idx, None, Some(position))?;
+    update_stats(storage, None, Some(position))?;
 
     Ok(())
 }
@@ -109,8 +368,28 @@ pub fn create_position(
 pub struct Position {
     pub idx: Uint128,
     pub owner: CanonicalAddr,
-    pub collateral: AssetRaw,
-    pub asset: AssetRaw,
+    /// basket of collaterals, one entry per collateral asset
+    pub collateral: Vec<AssetRaw>,
+    /// minted assets, one entry per asset
+    pub debt: Vec<Debt>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Debt {
+    pub info: AssetInfoRaw,
+    pub amount: Uint128,
+    /// asset fee index at the time the stability fee was last settled
+    pub fee_index: Decimal,
+}
+
+impl Position {
+    pub fn find_debt(&self, asset_info: &AssetInfoRaw) -> Option<&Debt> {
+        self.debt.iter().find(|debt| debt.info.equal(asset_info))
+    }
+
+    pub fn find_debt_mut(&mut self, asset_info: &AssetInfoRaw) -> Option<&mut Debt> {
+        self.debt.iter_mut().find(|debt| debt.info.equal(asset_info))
+    }
 }
 
 /// store position with idx
@@ -119,11 +398,184 @@ pub fn store_position(
     idx: Uint128,
     position: &Position,
 ) -> StdResult<()> {
+    let prev_position: Position = read_position(storage, idx)?;
+    update_asset_indexer(storage, idx, Some(&prev_position), Some(position))?;
+    update_stats(storage, Some(&prev_position), Some(position))?;
+
     let mut position_bucket: Bucket<Position> = Bucket::new(storage, PREFIX_POSITION);
     position_bucket.save(&idx.u128().to_be_bytes(), position)?;
     Ok(())
 }
 
+/// change the owner of the position and move its by_user index entry
+pub fn store_position_owner(
+    storage: &mut dyn Storage,
+    idx: Uint128,
+    owner: &CanonicalAddr,
+) -> StdResult<()> {
+    let mut position: Position = read_position(storage, idx)?;
+
+    let mut position_indexer_by_user: Bucket<bool> =
+        Bucket::multilevel(storage, &[PREFIX_INDEX_BY_USER, position.owner.as_slice()]);
+    position_indexer_by_user.remove(&idx.u128().to_be_bytes());
+
+    let mut position_indexer_by_user: Bucket<bool> =
+        Bucket::multilevel(storage, &[PREFIX_INDEX_BY_USER, owner.as_slice()]);
+    position_indexer_by_user.save(&idx.u128().to_be_bytes(), &true)?;
+
+    position.owner = owner.clone();
+    let mut position_bucket: Bucket<Position> = Bucket::new(storage, PREFIX_POSITION);
+    position_bucket.save(&idx.u128().to_be_bytes(), &position)
+}
+
+/// Swap of a leverage or deleverage waiting for its reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingSwap {
+    pub position_idx: Uint128,
+    /// asset bought by the swap
+    pub ask_info: AssetInfoRaw,
+    /// contract balance of the ask asset before the swap
+    pub prev_balance: Uint128,
+    /// amount of the ask asset deposited as collateral before the swap,
+    /// so deposits made by the pair during the swap are not taken as bought
+    pub prev_deposited: Uint128,
+    pub min_out: Uint128,
+    /// the bought asset repays a self liquidation
+    pub self_liquidation: bool,
+    /// collateral ratio before a self liquidation, which may stay below the minimum
+    /// as long as it does not get worse
+    pub prev_ratio: Option<Decimal>,
+}
+
+pub fn store_pending_swap(storage: &mut dyn Storage, pending_swap: &PendingSwap) -> StdResult<()> {
+    singleton(storage, KEY_PENDING_SWAP).save(pending_swap)
+}
+
+pub fn read_pending_swap(storage: &dyn Storage) -> StdResult<Option<PendingSwap>> {
+    singleton_read(storage, KEY_PENDING_SWAP).may_load()
+}
+
+pub fn remove_pending_swap(storage: &mut dyn Storage) {
+    singleton::<PendingSwap>(storage, KEY_PENDING_SWAP).remove()
+}
+
+/// Flash mint waiting for the receiver callback to repay it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingFlashMint {
+    pub asset_info: AssetInfoRaw,
+    pub amount: Uint128,
+    pub fee: Uint128,
+    /// contract balance of the asset before the mint
+    pub prev_balance: Uint128,
+    /// amount of the asset deposited as collateral before the mint,
+    /// so deposits made during the callback are not taken as repayment
+    pub prev_deposited: Uint128,
+}
+
+pub fn store_pending_flash_mint(
+    storage: &mut dyn Storage,
+    pending_flash_mint: &PendingFlashMint,
+) -> StdResult<()> {
+    singleton(storage, KEY_PENDING_FLASH_MINT).save(pending_flash_mint)
+}
+
+pub fn read_pending_flash_mint(storage: &dyn Storage) -> StdResult<Option<PendingFlashMint>> {
+    singleton_read(storage, KEY_PENDING_FLASH_MINT).may_load()
+}
+
+pub fn remove_pending_flash_mint(storage: &mut dyn Storage) {
+    singleton::<PendingFlashMint>(storage, KEY_PENDING_FLASH_MINT).remove()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorApproval {
+    pub operator: CanonicalAddr,
+    /// approval covers every position of the owner if not set
+    pub position_idx: Option<Uint128>,
+    /// approval covers every operation if not set
+    pub operations: Option<Vec<Operation>>,
+    /// block time in seconds after which the approval lapses
+    pub expires: Option<u64>,
+}
+
+impl OperatorApproval {
+    pub fn allows(&self, operation: Operation, time: u64) -> bool {
+        let expired: bool = matches!(self.expires, Some(expires) if time >= expires);
+        let covered: bool = match &self.operations {
+            Some(operations) => operations.contains(&operation),
+            None => true,
+        };
+
+        !expired && covered
+    }
+}
+
+fn approval_key(position_idx: Option<Uint128>) -> Vec<u8> {
+    match position_idx {
+        Some(idx) => idx.u128().to_be_bytes().to_vec(),
+        None => b"all".to_vec(),
+    }
+}
+
+pub fn store_operator_approval(
+    storage: &mut dyn Storage,
+    owner: &CanonicalAddr,
+    approval: &OperatorApproval,
+) -> StdResult<()> {
+    let mut approval_bucket: Bucket<OperatorApproval> = Bucket::multilevel(
+        storage,
+        &[
+            PREFIX_OPERATOR_APPROVAL,
+            owner.as_slice(),
+            approval.operator.as_slice(),
+        ],
+    );
+    approval_bucket.save(&approval_key(approval.position_idx), approval)
+}
+
+pub fn remove_operator_approval(
+    storage: &mut dyn Storage,
+    owner: &CanonicalAddr,
+    operator: &CanonicalAddr,
+    position_idx: Option<Uint128>,
+) {
+    let mut approval_bucket: Bucket<OperatorApproval> = Bucket::multilevel(
+        storage,
+        &[PREFIX_OPERATOR_APPROVAL, owner.as_slice(), operator.as_slice()],
+    );
+    approval_bucket.remove(&approval_key(position_idx))
+}
+
+pub fn read_operator_approval(
+    storage: &dyn Storage,
+    owner: &CanonicalAddr,
+    operator: &CanonicalAddr,
+    position_idx: Option<Uint128>,
+) -> StdResult<Option<OperatorApproval>> {
+    let approval_bucket: ReadonlyBucket<OperatorApproval> = ReadonlyBucket::multilevel(
+        storage,
+        &[PREFIX_OPERATOR_APPROVAL, owner.as_slice(), operator.as_slice()],
+    );
+    approval_bucket.may_load(&approval_key(position_idx))
+}
+
+/// every approval granted by the owner, across all operators
+pub fn read_operator_approvals(
+    storage: &dyn Storage,
+    owner: &CanonicalAddr,
+) -> StdResult<Vec<OperatorApproval>> {
+    let approval_bucket: ReadonlyBucket<OperatorApproval> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_OPERATOR_APPROVAL, owner.as_slice()]);
+
+    approval_bucket
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
 /// read position from store with position idx
 pub fn read_position(storage: &dyn Storage, idx: Uint128) -> StdResult<Position> {
     let position_bucket: ReadonlyBucket<Position> = ReadonlyBucket::new(storage, PREFIX_POSITION);
@@ -157,6 +609,58 @@ pub fn read_positions(
         .collect()
 }
 
+pub fn read_positions_with_user_indexer(
+    storage: &dyn Storage,
+    position_owner: &CanonicalAddr,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<Vec<Position>> {
+    let position_indexer: ReadonlyBucket<bool> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_INDEX_BY_USER, position_owner.as_slice()]);
+
+    read_indexed_positions(storage, position_indexer, start_after, limit, order_by)
+}
+
+pub fn read_positions_with_asset_indexer(
+    storage: &dyn Storage,
+    asset_info: &AssetInfoRaw,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<Vec<Position>> {
+    let position_indexer: ReadonlyBucket<bool> =
+        ReadonlyBucket::multilevel(storage, &[PREFIX_INDEX_BY_ASSET, asset_info.as_bytes()]);
+
+    read_indexed_positions(storage, position_indexer, start_after, limit, order_by)
+}
+
+fn read_indexed_positions(
+    storage: &dyn Storage,
+    position_indexer: ReadonlyBucket<bool>,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+    order_by: Option<OrderBy>,
+) -> StdResult<Vec<Position>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let (start, end, order_by) = match order_by {
+        Some(OrderBy::Asc) => (calc_range_start(start_after), None, OrderBy::Asc),
+        _ => (None, calc_range_end(start_after), OrderBy::Desc),
+    };
+
+    position_indexer
+        .range(start.as_deref(), end.as_deref(), order_by.into())
+        .take(limit)
+        .map(|item| {
+            let (k, _) = item?;
+            let idx: [u8; 16] = k
+                .try_into()
+                .map_err(|_| StdError::generic_err("invalid position index"))?;
+            read_position(storage, Uint128::from(u128::from_be_bytes(idx)))
+        })
+        .collect()
+}
+
 // this will set the first key after the provided key, by appending a 1 byte
 fn calc_range_start(start_after: Option<Uint128>) -> Option<Vec<u8>> {
     start_after.map(|idx| {
@@ -171,6 +675,32 @@ fn calc_range_end(start_after: Option<Uint128>) -> Option<Vec<u8>> {
     start_after.map(|idx| idx.u128().to_be_bytes().to_vec())
 }
 
+/// position indexes holding the collateral, in ascending order
+pub fn read_position_idxs_with_collateral_indexer(
+    storage: &dyn Storage,
+    collateral_info: &AssetInfoRaw,
+    start_after: Option<Uint128>,
+    limit: Option<u32>,
+) -> StdResult<Vec<Uint128>> {
+    let position_indexer: ReadonlyBucket<bool> = ReadonlyBucket::multilevel(
+        storage,
+        &[PREFIX_INDEX_BY_COLLATERAL, collateral_info.as_bytes()],
+    );
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    position_indexer
+        .range(calc_range_start(start_after).as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (k, _) = item?;
+            let idx: [u8; 16] = k
+                .try_into()
+                .map_err(|_| StdError::generic_err("invalid position index"))?;
+            Ok(Uint128::from(u128::from_be_bytes(idx)))
+        })
+        .collect()
+}
+
 /// remove position with idx
 pub fn remove_position(storage: &mut dyn Storage, idx: Uint128) -> StdResult<()> {
     let position: Position = read_position(storage, idx)?;
@@ -183,11 +713,164 @@ pub fn remove_position(storage: &mut dyn Storage, idx: Uint128) -> StdResult<()>
     position_indexer_by_user.remove(&idx.u128().to_be_bytes());
 
     // remove indexer
-    let mut position_indexer_by_asset: Bucket<bool> = Bucket::multilevel(
-        storage,
-        &[PREFIX_INDEX_BY_ASSET, position.asset.info.as_bytes()],
-    );
-    position_indexer_by_asset.remove(&idx.u128().to_be_bytes());
+    update_asset_indexer(storage, idx, Some(&position), None)?;
+    update_stats(storage, Some(&position), None)?;
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetStats {
+    pub info: AssetInfoRaw,
+    pub total_minted: Uint128,
+    pub position_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralStats {
+    pub info: AssetInfoRaw,
+    pub total_deposited: Uint128,
+}
+
+pub fn read_asset_stats(storage: &dyn Storage, asset_info: &AssetInfoRaw) -> StdResult<AssetStats> {
+    let stats_bucket: ReadonlyBucket<AssetStats> =
+        ReadonlyBucket::new(storage, PREFIX_ASSET_STATS);
+    Ok(stats_bucket
+        .may_load(asset_info.as_bytes())?
+        .unwrap_or(AssetStats {
+            info: asset_info.clone(),
+            total_minted: Uint128::zero(),
+            position_count: 0,
+        }))
+}
+
+pub fn read_collateral_stats(
+    storage: &dyn Storage,
+    collateral_info: &AssetInfoRaw,
+) -> StdResult<CollateralStats> {
+    let stats_bucket: ReadonlyBucket<CollateralStats> =
+        ReadonlyBucket::new(storage, PREFIX_COLLATERAL_STATS);
+    Ok(stats_bucket
+        .may_load(collateral_info.as_bytes())?
+        .unwrap_or(CollateralStats {
+            info: collateral_info.clone(),
+            total_deposited: Uint128::zero(),
+        }))
+}
+
+pub fn read_all_asset_stats(storage: &dyn Storage) -> StdResult<Vec<AssetStats>> {
+    let stats_bucket: ReadonlyBucket<AssetStats> =
+        ReadonlyBucket::new(storage, PREFIX_ASSET_STATS);
+    stats_bucket
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+pub fn read_all_collateral_stats(storage: &dyn Storage) -> StdResult<Vec<CollateralStats>> {
+    let stats_bucket: ReadonlyBucket<CollateralStats> =
+        ReadonlyBucket::new(storage, PREFIX_COLLATERAL_STATS);
+    stats_bucket
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+// keep the by_asset and by_collateral indexes in line with the position
+fn update_asset_indexer(
+    storage: &mut dyn Storage,
+    idx: Uint128,
+    prev: Option<&Position>,
+    next: Option<&Position>,
+) -> StdResult<()> {
+    if let Some(prev) = prev {
+        for debt in prev.debt.iter() {
+            let mut position_indexer_by_asset: Bucket<bool> =
+                Bucket::multilevel(storage, &[PREFIX_INDEX_BY_ASSET, debt.info.as_bytes()]);
+            position_indexer_by_asset.remove(&idx.u128().to_be_bytes());
+        }
+    }
+
+    if let Some(next) = next {
+        for debt in next.debt.iter() {
+            let mut position_indexer_by_asset: Bucket<bool> =
+                Bucket::multilevel(storage, &[PREFIX_INDEX_BY_ASSET, debt.info.as_bytes()]);
+            position_indexer_by_asset.save(&idx.u128().to_be_bytes(), &true)?;
+        }
+    }
+
+    // collaterals are indexed the same way
+    if let Some(prev) = prev {
+        for collateral in prev.collateral.iter() {
+            let mut position_indexer_by_collateral: Bucket<bool> = Bucket::multilevel(
+                storage,
+                &[PREFIX_INDEX_BY_COLLATERAL, collateral.info.as_bytes()],
+            );
+            position_indexer_by_collateral.remove(&idx.u128().to_be_bytes());
+        }
+    }
+
+    if let Some(next) = next {
+        for collateral in next.collateral.iter() {
+            let mut position_indexer_by_collateral: Bucket<bool> = Bucket::multilevel(
+                storage,
+                &[PREFIX_INDEX_BY_COLLATERAL, collateral.info.as_bytes()],
+            );
+            position_indexer_by_collateral.save(&idx.u128().to_be_bytes(), &true)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// move the position amounts out of the running totals for `prev`
+/// and into the running totals for `next`
+fn update_stats(
+    storage: &mut dyn Storage,
+    prev: Option<&Position>,
+    next: Option<&Position>,
+) -> StdResult<()> {
+    if let Some(prev) = prev {
+        for debt in prev.debt.iter() {
+            let mut asset_stats = read_asset_stats(storage, &debt.info)?;
+            asset_stats.total_minted = asset_stats.total_minted.checked_sub(debt.amount)?;
+            asset_stats.position_count -= 1;
+            Bucket::new(storage, PREFIX_ASSET_STATS).save(debt.info.as_bytes(), &asset_stats)?;
+        }
+
+        for collateral in prev.collateral.iter() {
+            let mut collateral_stats = read_collateral_stats(storage, &collateral.info)?;
+            collateral_stats.total_deposited = collateral_stats
+                .total_deposited
+                .checked_sub(collateral.amount)?;
+            Bucket::new(storage, PREFIX_COLLATERAL_STATS)
+                .save(collateral.info.as_bytes(), &collateral_stats)?;
+        }
+    }
+
+    if let Some(next) = next {
+        for debt in next.debt.iter() {
+            let mut asset_stats = read_asset_stats(storage, &debt.info)?;
+            asset_stats.total_minted = asset_stats.total_minted.checked_add(debt.amount)?;
+            asset_stats.position_count += 1;
+            Bucket::new(storage, PREFIX_ASSET_STATS).save(debt.info.as_bytes(), &asset_stats)?;
+        }
+
+        for collateral in next.collateral.iter() {
+            let mut collateral_stats = read_collateral_stats(storage, &collateral.info)?;
+            collateral_stats.total_deposited = collateral_stats
+                .total_deposited
+                .checked_add(collateral.amount)?;
+            Bucket::new(storage, PREFIX_COLLATERAL_STATS)
+                .save(collateral.info.as_bytes(), &collateral_stats)?;
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,848 @@
+use crate::contract::{execute, query, reply, FLASH_MINT_REPLY_ID};
+use crate::error::ContractError;
+use crate::querier::PRICE_EXPIRE_TIME;
+use crate::state::{create_position, read_config, Position};
+use crate::testing::mock_querier::CollateralInfo;
+use crate::testing::{
+    attribute, open_position_msg, register_asset, setup, MockDeps, ASSET, BASE_DENOM, OWNER, USER,
+};
+use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    coin, from_json, to_json_binary, Api, Binary, CosmosMsg, Decimal, Reply, SubMsgResponse,
+    SubMsgResult, Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use melange_protocol::asset::{Asset, AssetInfo};
+use melange_protocol::mint::{
+    AssetConfigResponse, AssetStatsResponse, CollateralStatsResponse, ConfigResponse, ExecuteMsg,
+    FeeSchedule, FeeScheduleResponse, Operation, PauseInfo, PauseStatusResponse,
+    PendingOwnerResponse, ProtocolStatsResponse, QueryMsg, QueuedChangeResponse,
+    QueuedChangesResponse, TimelockedChange,
+};
+
+const GUARDIAN: &str = "guardian0000";
+
+fn update_guardian(guardian: Option<String>, remove_guardian: Option<bool>) -> ExecuteMsg {
+    ExecuteMsg::UpdateConfig {
+        collector: None,
+        melange_factory: None,
+        lock: None,
+        token_code_id: None,
+        staking: None,
+        guardian,
+        remove_guardian,
+        revoked_collateral_grace_period: None,
+        revoked_collateral_ratio: None,
+    }
+}
+
+fn update_fee_schedule(fee_schedule: FeeSchedule) -> ExecuteMsg {
+    ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: Some(fee_schedule),
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    }
+}
+
+#[test]
+fn stats_values_normalized_by_decimals() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 18u8, Decimal::percent(200));
+
+    // 1 SEI at 200% mints 0.25 of the asset, worth 0.5 SEI
+    let msg = ExecuteMsg::OpenPosition {
+        collateral: Asset {
+            info: AssetInfo::NativeToken {
+                denom: BASE_DENOM.to_string(),
+            },
+            amount: Uint128::from(1_000_000u128),
+        },
+        asset_info: AssetInfo::Token {
+            contract_addr: ASSET.to_string(),
+        },
+        collateral_ratio: Decimal::percent(200),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res: AssetStatsResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::AssetStats {
+                asset_token: ASSET.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        AssetStatsResponse {
+            asset_token: ASSET.to_string(),
+            total_minted: Uint128::from(250_000_000_000_000_000u128),
+            position_count: 1,
+            total_minted_value: Uint128::from(500_000u128),
+        }
+    );
+
+    let res: ProtocolStatsResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::ProtocolStats {}).unwrap()).unwrap();
+    assert_eq!(res.assets.len(), 1);
+    assert_eq!(res.total_debt_value, Uint128::from(500_000u128));
+    assert_eq!(res.total_collateral_value, Uint128::from(1_000_000u128));
+}
+
+#[test]
+fn stats_value_unpriceable_entries_at_zero() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier
+        .with_collateral("uatom", CollateralInfo::new(Decimal::percent(1000)));
+
+    let msg = ExecuteMsg::OpenPosition {
+        collateral: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+            amount: Uint128::from(1_000_000u128),
+        },
+        asset_info: AssetInfo::Token {
+            contract_addr: ASSET.to_string(),
+        },
+        collateral_ratio: Decimal::percent(200),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let res: ProtocolStatsResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::ProtocolStats {}).unwrap()).unwrap();
+    assert_eq!(res.total_debt_value, Uint128::from(5_000_000u128));
+    assert_eq!(res.total_collateral_value, Uint128::from(10_000_000u128));
+
+    // neither oracle prices them anymore; the query still answers
+    deps.querier.remove_price(ASSET);
+    deps.querier.remove_price("uatom");
+    let res: ProtocolStatsResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::ProtocolStats {}).unwrap()).unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![CollateralStatsResponse {
+            collateral: AssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+            total_deposited: Uint128::from(1_000_000u128),
+            total_deposited_value: Uint128::zero(),
+        }]
+    );
+    assert_eq!(res.assets[0].total_minted, Uint128::from(5_000_000u128));
+    assert_eq!(res.assets[0].total_minted_value, Uint128::zero());
+    assert_eq!(res.total_debt_value, Uint128::zero());
+    assert_eq!(res.total_collateral_value, Uint128::zero());
+}
+
+#[test]
+fn fee_schedule_defaults_to_protocol_fee() {
+    let mut deps = setup();
+    deps.querier.with_token(ASSET, 6u8);
+
+    let msg = ExecuteMsg::RegisterAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: Decimal::percent(20),
+        min_collateral_ratio: Decimal::percent(150),
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res: FeeScheduleResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::FeeSchedule {
+                asset_token: ASSET.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.fee_schedule,
+        FeeSchedule {
+            open_fee_rate: Decimal::zero(),
+            mint_fee_rate: Decimal::zero(),
+            burn_fee_rate: Decimal::percent(1),
+            liquidation_fee_rate: Decimal::percent(1),
+            flash_mint_fee_rate: Decimal::percent(1),
+            self_liquidation_fee_rate: Decimal::percent(1),
+        }
+    );
+}
+
+#[test]
+fn fee_schedule_rates_validated() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+
+    let msg = update_fee_schedule(FeeSchedule {
+        mint_fee_rate: Decimal::one(),
+        ..FeeSchedule::default()
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidFeeRate {
+            fee_rate: Decimal::one()
+        }
+    );
+
+    // self liquidation can not cost more than being liquidated
+    let msg = update_fee_schedule(FeeSchedule {
+        liquidation_fee_rate: Decimal::percent(1),
+        self_liquidation_fee_rate: Decimal::percent(2),
+        ..FeeSchedule::default()
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidFeeRate {
+            fee_rate: Decimal::percent(2)
+        }
+    );
+
+    let msg = update_fee_schedule(FeeSchedule::default());
+    let err = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn guardian_pauses_and_only_owner_unpauses() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = update_guardian(Some(GUARDIAN.to_string()), None);
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::Pause {
+        asset_token: Some(ASSET.to_string()),
+        operations: Some(vec![Operation::Open]),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(GUARDIAN, &[]), msg).unwrap();
+
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            operation: "open".to_string()
+        }
+    );
+
+    let res: PauseStatusResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::PauseStatus {
+                asset_token: Some(ASSET.to_string()),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        PauseStatusResponse {
+            global: PauseInfo::default(),
+            asset: Some(PauseInfo {
+                all: false,
+                operations: vec![Operation::Open],
+            }),
+        }
+    );
+
+    let unpause = ExecuteMsg::Unpause {
+        asset_token: Some(ASSET.to_string()),
+        operations: None,
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(GUARDIAN, &[]),
+        unpause.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), unpause).unwrap();
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap();
+
+    // a global pause of every operation covers every asset
+    let pause = ExecuteMsg::Pause {
+        asset_token: None,
+        operations: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(GUARDIAN, &[]), pause).unwrap();
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            operation: "open".to_string()
+        }
+    );
+}
+
+#[test]
+fn global_pause_covers_positions_without_debt() {
+    let mut deps = setup();
+    let position = Position {
+        idx: Uint128::from(1u128),
+        owner: deps.api.addr_canonicalize(USER).unwrap(),
+        collateral: vec![],
+        debt: vec![],
+    };
+    create_position(&mut deps.storage, position.idx, &position).unwrap();
+
+    let pause = ExecuteMsg::Pause {
+        asset_token: None,
+        operations: Some(vec![Operation::Deposit, Operation::Withdraw]),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), pause).unwrap();
+
+    let collateral = Asset {
+        info: AssetInfo::NativeToken {
+            denom: BASE_DENOM.to_string(),
+        },
+        amount: Uint128::from(1_000u128),
+    };
+    let msg = ExecuteMsg::Deposit {
+        position_idx: position.idx,
+        collateral: collateral.clone(),
+    };
+    let info = mock_info(USER, &[coin(1_000u128, BASE_DENOM)]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            operation: "deposit".to_string()
+        }
+    );
+
+    let msg = ExecuteMsg::Withdraw {
+        position_idx: position.idx,
+        collateral: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            operation: "withdraw".to_string()
+        }
+    );
+
+    let msg = ExecuteMsg::SwapCollateral {
+        position_idx: position.idx,
+        new_collateral: collateral,
+        old_collateral: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            operation: "deposit".to_string()
+        }
+    );
+}
+
+#[test]
+fn remove_guardian_clears_guardian() {
+    let mut deps = setup();
+    let msg = update_guardian(Some(GUARDIAN.to_string()), None);
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    assert_eq!(
+        read_config(&deps.storage).unwrap().guardian,
+        Some(deps.api.addr_canonicalize(GUARDIAN).unwrap())
+    );
+
+    // removal takes precedence over a guardian set in the same message
+    let msg = update_guardian(Some("guardian0001".to_string()), Some(true));
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    assert_eq!(read_config(&deps.storage).unwrap().guardian, None);
+
+    let msg = ExecuteMsg::Pause {
+        asset_token: None,
+        operations: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(GUARDIAN, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn ownership_transferred_in_two_steps() {
+    let mut deps = setup();
+
+    let msg = ExecuteMsg::ProposeNewOwner {
+        owner: "owner0001".to_string(),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res: PendingOwnerResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::PendingOwner {}).unwrap()).unwrap();
+    assert_eq!(res.pending_owner, Some("owner0001".to_string()));
+
+    // the owner does not change until the proposed owner claims
+    let msg = ExecuteMsg::ClaimOwnership {};
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    assert_eq!(
+        read_config(&deps.storage).unwrap().owner,
+        deps.api.addr_canonicalize(OWNER).unwrap()
+    );
+
+    execute(deps.as_mut(), mock_env(), mock_info("owner0001", &[]), msg).unwrap();
+    assert_eq!(
+        read_config(&deps.storage).unwrap().owner,
+        deps.api.addr_canonicalize("owner0001").unwrap()
+    );
+    let res: PendingOwnerResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::PendingOwner {}).unwrap()).unwrap();
+    assert_eq!(res.pending_owner, None);
+
+    let msg = ExecuteMsg::ProposeNewOwner {
+        owner: USER.to_string(),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn ownership_proposal_cancelled_by_owner() {
+    let mut deps = setup();
+
+    let msg = ExecuteMsg::ProposeNewOwner {
+        owner: "owner0001".to_string(),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::CancelOwnershipProposal {};
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res: PendingOwnerResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::PendingOwner {}).unwrap()).unwrap();
+    assert_eq!(res.pending_owner, None);
+
+    // the withdrawn proposal can no longer be claimed
+    let msg = ExecuteMsg::ClaimOwnership {};
+    let err = execute(deps.as_mut(), mock_env(), mock_info("owner0001", &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    assert_eq!(
+        read_config(&deps.storage).unwrap().owner,
+        deps.api.addr_canonicalize(OWNER).unwrap()
+    );
+}
+
+#[test]
+fn migration_freezes_the_asset_at_the_end_price() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+
+    let msg = ExecuteMsg::RegisterMigration {
+        asset_token: ASSET.to_string(),
+        end_price: Decimal::percent(80),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let msg = QueryMsg::AssetConfig {
+        asset_token: ASSET.to_string(),
+    };
+    let res: AssetConfigResponse =
+        from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+    assert_eq!(res.end_price, Some(Decimal::percent(80)));
+    assert_eq!(res.min_collateral_ratio, Decimal::one());
+
+    // no new debt can be minted against a migrated asset
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::MigratedAsset {});
+}
+
+#[test]
+fn config_query() {
+    let mut deps = setup();
+    let msg = update_guardian(Some(GUARDIAN.to_string()), None);
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res: ConfigResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(
+        res,
+        ConfigResponse {
+            owner: OWNER.to_string(),
+            oracle: "oracle0000".to_string(),
+            collector: "collector0000".to_string(),
+            collateral_oracle: "collateraloracle0000".to_string(),
+            staking: "staking0000".to_string(),
+            melange_factory: "factory0000".to_string(),
+            lock: "lock0000".to_string(),
+            base_denom: BASE_DENOM.to_string(),
+            base_decimals: 6u8,
+            token_code_id: 10u64,
+            protocol_fee_rate: Decimal::percent(1),
+            guardian: Some(GUARDIAN.to_string()),
+            timelock_period: 86400u64,
+            revoked_collateral_grace_period: 604800u64,
+            revoked_collateral_ratio: Decimal::percent(200),
+        }
+    );
+}
+
+#[test]
+fn sensitive_changes_wait_for_timelock() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+
+    // lowering the ratio directly is rejected
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: Some(Decimal::percent(130)),
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::TimelockRequired {});
+
+    let now: u64 = mock_env().block.time.seconds();
+    let msg = ExecuteMsg::QueueChange {
+        change: TimelockedChange::ProtocolFeeRate {
+            protocol_fee_rate: Decimal::percent(2),
+        },
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::QueueChange {
+        change: TimelockedChange::MinCollateralRatio {
+            asset_token: ASSET.to_string(),
+            min_collateral_ratio: Decimal::percent(130),
+        },
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let res: QueuedChangesResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::QueuedChanges {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.changes[0],
+        QueuedChangeResponse {
+            id: 1u64,
+            change: TimelockedChange::ProtocolFeeRate {
+                protocol_fee_rate: Decimal::percent(2),
+            },
+            executable_at: now + 86400u64,
+        }
+    );
+    assert_eq!(res.changes.len(), 2);
+
+    let msg = ExecuteMsg::ExecuteChange { id: 1u64 };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::Timelocked {
+            executable_at: now + 86400u64
+        }
+    );
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(86400u64);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(USER, &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(OWNER, &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        read_config(&deps.storage).unwrap().protocol_fee_rate,
+        Decimal::percent(2)
+    );
+
+    // executed changes are removed
+    execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap_err();
+
+    // cancelled changes can not be executed
+    let msg = ExecuteMsg::CancelChange { id: 2u64 };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::ExecuteChange { id: 2u64 };
+    execute(deps.as_mut(), env, mock_info(OWNER, &[]), msg).unwrap_err();
+    let res: QueuedChangesResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::QueuedChanges {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(res.changes.is_empty());
+}
+
+#[test]
+fn register_asset_typed_errors() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_token("asset0001", 6u8);
+
+    let register = |asset_token: &str, auction_discount: Decimal, min_collateral_ratio: Decimal| {
+        ExecuteMsg::RegisterAsset {
+            asset_token: asset_token.to_string(),
+            auction_discount,
+            min_collateral_ratio,
+            stability_fee_rate: None,
+            fee_schedule: None,
+            debt_ceiling: None,
+        }
+    };
+
+    let msg = register(ASSET, Decimal::percent(20), Decimal::percent(150));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::AssetAlreadyRegistered {});
+
+    let msg = register("asset0001", Decimal::percent(20), Decimal::percent(110));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMinCollateralRatio {
+            min_allowed: Decimal::percent(120),
+            actual: Decimal::percent(110),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "min_collateral_ratio must be bigger or equal than 1.2; got 1.1"
+    );
+
+    let msg = register("asset0001", Decimal::percent(120), Decimal::percent(150));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidAuctionDiscount {
+            auction_discount: Decimal::percent(120)
+        }
+    );
+
+    let msg = register("asset0001", Decimal::percent(20), Decimal::percent(150));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    assert_eq!(err.to_string(), "Unauthorized");
+
+    deps.querier.with_token("asset0002", 19u8);
+    let msg = register("asset0002", Decimal::percent(20), Decimal::percent(150));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidDecimals {
+            max_decimals: 18,
+            decimals: 19,
+        }
+    );
+    assert_eq!(err.to_string(), "decimals must be at most 18; got 19");
+
+    let msg = ExecuteMsg::RegisterNativeAsset {
+        subdenom: "masset".to_string(),
+        decimals: 19u8,
+        auction_discount: Decimal::percent(20),
+        min_collateral_ratio: Decimal::percent(150),
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidDecimals {
+            max_decimals: 18,
+            decimals: 19,
+        }
+    );
+}
+
+#[test]
+fn asset_limits_removed() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+
+    let update = |debt_ceiling: Option<Uint128>,
+                  price_expire_time: Option<u64>,
+                  remove: Option<bool>| ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling,
+        remove_debt_ceiling: remove,
+        price_expire_time,
+        remove_price_expire_time: remove,
+        market_hours: None,
+    };
+    let asset_config = |deps: &MockDeps| -> AssetConfigResponse {
+        let msg = QueryMsg::AssetConfig {
+            asset_token: ASSET.to_string(),
+        };
+        from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+    };
+
+    let msg = update(Some(Uint128::from(1_000u128)), Some(300u64), None);
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let res = asset_config(&deps);
+    assert_eq!(res.debt_ceiling, Some(Uint128::from(1_000u128)));
+    assert_eq!(res.price_expire_time, 300u64);
+
+    // removing takes precedence over a value given alongside
+    let msg = update(Some(Uint128::from(2_000u128)), Some(600u64), Some(true));
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    let res = asset_config(&deps);
+    assert_eq!(res.debt_ceiling, None);
+    assert_eq!(res.price_expire_time, PRICE_EXPIRE_TIME);
+}
+
+const RECEIVER: &str = "receiver0000";
+
+/// ASSET registered with a 1% flash mint fee
+fn setup_flash_mint() -> MockDeps {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = update_fee_schedule(FeeSchedule {
+        flash_mint_fee_rate: Decimal::percent(1),
+        ..FeeSchedule::default()
+    });
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    deps
+}
+
+fn flash_mint(amount: u128) -> ExecuteMsg {
+    ExecuteMsg::FlashMint {
+        asset_token: ASSET.to_string(),
+        amount: Uint128::from(amount),
+        callback: Binary::from(br#"{"arbitrage":{}}"#),
+    }
+}
+
+fn flash_mint_reply() -> Reply {
+    Reply {
+        id: FLASH_MINT_REPLY_ID,
+        result: SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+        }),
+    }
+}
+
+#[test]
+fn flash_mint_burned_once_repaid() {
+    let mut deps = setup_flash_mint();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RECEIVER, &[]),
+        flash_mint(1_000_000u128),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: ASSET.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Mint {
+                recipient: RECEIVER.to_string(),
+                amount: Uint128::from(1_000_000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    assert_eq!(res.messages[1].id, FLASH_MINT_REPLY_ID);
+
+    // the callback can not flash mint again
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RECEIVER, &[]),
+        flash_mint(1_000_000u128),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::FlashMintInProgress {});
+
+    // anything paid over the amount goes to the collector
+    deps.querier
+        .with_token_balance(ASSET, MOCK_CONTRACT_ADDR, Uint128::from(1_012_000u128));
+    let res = reply(deps.as_mut(), mock_env(), flash_mint_reply()).unwrap();
+    assert_eq!(attribute(&res, "burn_amount"), "1000000asset0000");
+    assert_eq!(attribute(&res, "flash_mint_fee"), "12000asset0000");
+}
+
+#[test]
+fn flash_mint_reverted_unless_repaid_with_fee() {
+    let mut deps = setup_flash_mint();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(RECEIVER, &[]),
+        flash_mint(1_000_000u128),
+    )
+    .unwrap();
+    deps.querier
+        .with_token_balance(ASSET, MOCK_CONTRACT_ADDR, Uint128::from(1_005_000u128));
+
+    let err = reply(deps.as_mut(), mock_env(), flash_mint_reply()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::FlashMintNotRepaid {
+            required: Uint128::from(1_010_000u128),
+            repaid: Uint128::from(1_005_000u128),
+        }
+    );
+}
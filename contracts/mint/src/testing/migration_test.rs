@@ -0,0 +1,258 @@
+// buckets from cosmwasm-storage until the state moves to cw-storage-plus
+#![allow(deprecated)]
+
+use crate::contract::migrate;
+use crate::migration::{LegacyAssetConfig, LegacyCollateral, LegacyConfig, LegacyPosition};
+use crate::state::{
+    create_position, read_asset_config, read_asset_stats, read_collateral_stats, read_config,
+    read_position, AssetConfig, Debt, Position, KEY_CONFIG, PREFIX_ASSET_CONFIG, PREFIX_POSITION,
+};
+use crate::testing::{register_asset, setup, ASSET, BASE_DENOM, USER};
+use cosmwasm_std::testing::mock_env;
+use cosmwasm_std::{from_json, Api, Decimal, Uint128};
+use cosmwasm_storage::{singleton, Bucket};
+use melange_protocol::asset::{AssetInfoRaw, AssetRaw};
+use melange_protocol::mint::{FeeSchedule, MigrateMsg};
+
+fn collateral(denom: &str, amount: u128) -> AssetRaw {
+    AssetRaw {
+        info: AssetInfoRaw::NativeToken {
+            denom: denom.to_string(),
+        },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn migrate_msg() -> MigrateMsg {
+    MigrateMsg {
+        auction_discount: Decimal::percent(20),
+        base_decimals: 6u8,
+        timelock_period: 86400u64,
+        revoked_collateral_grace_period: 604800u64,
+        revoked_collateral_ratio: Decimal::percent(200),
+    }
+}
+
+#[test]
+fn legacy_config_completed() {
+    let mut deps = setup();
+    let config = read_config(&deps.storage).unwrap();
+    singleton(&mut deps.storage, KEY_CONFIG)
+        .save(&LegacyConfig {
+            owner: config.owner.clone(),
+            oracle: config.oracle.clone(),
+            collector: config.collector.clone(),
+            collateral_oracle: config.collateral_oracle.clone(),
+            staking: config.staking.clone(),
+            melange_factory: config.melange_factory.clone(),
+            lock: config.lock.clone(),
+            base_denom: config.base_denom.clone(),
+            token_code_id: config.token_code_id,
+            protocol_fee_rate: config.protocol_fee_rate,
+        })
+        .unwrap();
+    assert!(read_config(&deps.storage).is_err());
+
+    migrate(deps.as_mut(), mock_env(), migrate_msg()).unwrap();
+    assert_eq!(read_config(&deps.storage).unwrap(), config);
+
+    // a config in the current layout is kept as it is
+    let msg = MigrateMsg {
+        timelock_period: 3600u64,
+        ..migrate_msg()
+    };
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+    assert_eq!(read_config(&deps.storage).unwrap(), config);
+}
+
+#[test]
+fn migration_fails_on_undecodable_entries() {
+    let mut deps = setup();
+    let mut positions: Bucket<Uint128> = Bucket::new(&mut deps.storage, PREFIX_POSITION);
+    positions
+        .save(&1u128.to_be_bytes(), &Uint128::from(1u128))
+        .unwrap();
+
+    migrate(deps.as_mut(), mock_env(), migrate_msg()).unwrap_err();
+}
+
+#[test]
+fn legacy_positions_migrated_to_basket_layout() {
+    let mut deps = setup();
+    let owner = deps.api.addr_canonicalize(USER).unwrap();
+    let asset_info = AssetInfoRaw::Token {
+        contract_addr: deps.api.addr_canonicalize(ASSET).unwrap(),
+    };
+
+    let legacy_position = |idx: u128, collateral: LegacyCollateral| LegacyPosition {
+        idx: Uint128::from(idx),
+        owner: owner.clone(),
+        collateral,
+        asset: AssetRaw {
+            info: asset_info.clone(),
+            amount: Uint128::from(500u128),
+        },
+        fee_index: Decimal::zero(),
+    };
+    let mut legacy_positions: Bucket<LegacyPosition> =
+        Bucket::new(&mut deps.storage, PREFIX_POSITION);
+    legacy_positions
+        .save(
+            &1u128.to_be_bytes(),
+            &legacy_position(
+                1u128,
+                LegacyCollateral::Single(collateral(BASE_DENOM, 1000u128)),
+            ),
+        )
+        .unwrap();
+    legacy_positions
+        .save(
+            &2u128.to_be_bytes(),
+            &legacy_position(
+                2u128,
+                LegacyCollateral::Basket(vec![
+                    collateral(BASE_DENOM, 1000u128),
+                    collateral("uatom", 100u128),
+                ]),
+            ),
+        )
+        .unwrap();
+
+    // positions in the new layout are left as they are
+    let position = Position {
+        idx: Uint128::from(3u128),
+        owner: owner.clone(),
+        collateral: vec![collateral(BASE_DENOM, 2000u128)],
+        debt: vec![Debt {
+            info: asset_info.clone(),
+            amount: Uint128::from(1000u128),
+            fee_index: Decimal::one(),
+        }],
+    };
+    create_position(&mut deps.storage, Uint128::from(3u128), &position).unwrap();
+
+    let msg = migrate_msg();
+    migrate(deps.as_mut(), mock_env(), msg.clone()).unwrap();
+
+    assert_eq!(
+        read_position(&deps.storage, Uint128::from(1u128)).unwrap(),
+        Position {
+            idx: Uint128::from(1u128),
+            owner: owner.clone(),
+            collateral: vec![collateral(BASE_DENOM, 1000u128)],
+            debt: vec![Debt {
+                info: asset_info.clone(),
+                amount: Uint128::from(500u128),
+                fee_index: Decimal::zero(),
+            }],
+        }
+    );
+    assert_eq!(
+        read_position(&deps.storage, Uint128::from(2u128))
+            .unwrap()
+            .collateral,
+        vec![
+            collateral(BASE_DENOM, 1000u128),
+            collateral("uatom", 100u128)
+        ]
+    );
+    assert_eq!(
+        read_position(&deps.storage, Uint128::from(3u128)).unwrap(),
+        position
+    );
+
+    // the stats are rebuilt for the migrated positions only
+    let asset_stats = read_asset_stats(&deps.storage, &asset_info).unwrap();
+    assert_eq!(asset_stats.total_minted, Uint128::from(2000u128));
+    assert_eq!(asset_stats.position_count, 3u64);
+    let collateral_stats = read_collateral_stats(
+        &deps.storage,
+        &AssetInfoRaw::NativeToken {
+            denom: BASE_DENOM.to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(collateral_stats.total_deposited, Uint128::from(4000u128));
+
+    // migrating again changes nothing
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+    let asset_stats = read_asset_stats(&deps.storage, &asset_info).unwrap();
+    assert_eq!(asset_stats.total_minted, Uint128::from(2000u128));
+}
+
+#[test]
+fn legacy_asset_configs_completed() {
+    let mut deps = setup();
+    register_asset(&mut deps, "asset0001", 6u8, Decimal::one());
+    let registered_info = AssetInfoRaw::Token {
+        contract_addr: deps.api.addr_canonicalize("asset0001").unwrap(),
+    };
+    let registered = read_asset_config(&deps.storage, &registered_info).unwrap();
+
+    deps.querier.with_token(ASSET, 8u8);
+    let asset_info = AssetInfoRaw::Token {
+        contract_addr: deps.api.addr_canonicalize(ASSET).unwrap(),
+    };
+    let mut legacy_asset_configs: Bucket<LegacyAssetConfig> =
+        Bucket::new(&mut deps.storage, PREFIX_ASSET_CONFIG);
+    legacy_asset_configs
+        .save(
+            asset_info.as_bytes(),
+            &LegacyAssetConfig {
+                token: deps.api.addr_canonicalize(ASSET).unwrap(),
+                min_collateral_ratio: Decimal::percent(150),
+                end_price: None,
+            },
+        )
+        .unwrap();
+
+    let msg = migrate_msg();
+    migrate(deps.as_mut(), mock_env(), msg).unwrap();
+
+    // burns and auctions pay the protocol fee, as they did before the fee schedule
+    assert_eq!(
+        read_asset_config(&deps.storage, &asset_info).unwrap(),
+        AssetConfig {
+            info: asset_info.clone(),
+            decimals: 8u8,
+            auction_discount: Decimal::percent(20),
+            min_collateral_ratio: Decimal::percent(150),
+            end_price: None,
+            stability_fee_rate: Decimal::zero(),
+            fee_schedule: FeeSchedule {
+                burn_fee_rate: Decimal::percent(1),
+                liquidation_fee_rate: Decimal::percent(1),
+                flash_mint_fee_rate: Decimal::percent(1),
+                self_liquidation_fee_rate: Decimal::percent(1),
+                ..FeeSchedule::default()
+            },
+            debt_ceiling: None,
+            price_expire_time: None,
+            market_hours: None,
+        }
+    );
+    assert_eq!(
+        read_asset_config(&deps.storage, &registered_info).unwrap(),
+        registered
+    );
+}
+
+#[test]
+fn asset_config_fields_default_when_not_stored() {
+    let asset_config: AssetConfig = from_json(
+        br#"{
+            "info": {"NativeToken": {"denom": "factory/cosmos2contract/masset"}},
+            "auction_discount": "0.2",
+            "min_collateral_ratio": "1.5",
+            "end_price": null
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(asset_config.decimals, 6u8);
+    assert_eq!(asset_config.stability_fee_rate, Decimal::zero());
+    assert_eq!(asset_config.fee_schedule, FeeSchedule::default());
+    assert_eq!(asset_config.debt_ceiling, None);
+    assert_eq!(asset_config.price_expire_time, None);
+    assert_eq!(asset_config.market_hours, None);
+}
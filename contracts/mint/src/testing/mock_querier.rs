@@ -0,0 +1,305 @@
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_json, to_json_binary, Coin, ContractResult, Decimal, Empty, OwnedDeps, Querier,
+    QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
+};
+use cw20::{BalanceResponse as Cw20BalanceResponse, TokenInfoResponse};
+use melange_protocol::asset::AssetInfo;
+use melange_protocol::collateral_oracle::{CollateralInfoResponse, CollateralPriceResponse};
+use melange_protocol::oracle::PriceResponse;
+use melange_protocol::pair::PairInfo;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// mock_env block time, when the mocked prices are published by default
+pub const MOCK_PRICE_TIME: u64 = 1_571_797_419;
+
+/// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
+/// this uses our CustomQuerier.
+pub fn mock_dependencies(
+    contract_balance: &[Coin],
+) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    let custom_querier: WasmMockQuerier =
+        WasmMockQuerier::new(MockQuerier::new(&[(MOCK_CONTRACT_ADDR, contract_balance)]));
+
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier: custom_querier,
+        custom_query_type: PhantomData,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Price {
+        asset_token: String,
+        timeframe: Option<u64>,
+    },
+    CollateralPrice {
+        asset: String,
+        timeframe: Option<u64>,
+    },
+    CollateralAssetInfo {
+        asset: String,
+    },
+    TokenInfo {},
+    Balance {
+        address: String,
+    },
+    Pair {},
+}
+
+#[derive(Clone, Debug)]
+pub struct OraclePrice {
+    pub rate: Decimal,
+    pub confidence: Decimal,
+    pub quote_denom: Option<String>,
+    pub last_updated: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct CollateralInfo {
+    pub rate: Decimal,
+    pub confidence: Decimal,
+    pub multiplier: Decimal,
+    pub is_revoked: bool,
+    pub max_deposit: Option<Uint128>,
+    pub decimals: u8,
+    pub price_expire_time: Option<u64>,
+    pub last_updated: u64,
+}
+
+impl CollateralInfo {
+    pub fn new(rate: Decimal) -> Self {
+        CollateralInfo {
+            rate,
+            confidence: Decimal::zero(),
+            multiplier: Decimal::one(),
+            is_revoked: false,
+            max_deposit: None,
+            decimals: 6u8,
+            price_expire_time: None,
+            last_updated: MOCK_PRICE_TIME,
+        }
+    }
+}
+
+pub struct WasmMockQuerier {
+    base: MockQuerier<Empty>,
+    oracle_prices: HashMap<String, OraclePrice>,
+    collaterals: HashMap<String, CollateralInfo>,
+    token_decimals: HashMap<String, u8>,
+    token_balances: HashMap<String, HashMap<String, Uint128>>,
+    pairs: HashMap<String, [AssetInfo; 2]>,
+    /// block time the price ages are measured at
+    time: u64,
+    /// number of CollateralAssetInfo queries answered
+    collateral_info_queries: Cell<u64>,
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        // MockQuerier doesn't support Custom, so we ignore it completely here
+        let request: QueryRequest<Empty> = match from_json(bin_request) {
+            Ok(v) => v,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        self.handle_query(&request)
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
+        match &request {
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => match from_json(msg) {
+                Ok(QueryMsg::Price {
+                    asset_token,
+                    timeframe,
+                }) => match self.oracle_prices.get(&asset_token) {
+                    Some(price) if self.expired(price.last_updated, timeframe) => {
+                        query_err("Price is too old")
+                    }
+                    Some(price) => query_ok(&PriceResponse {
+                        rate: price.rate,
+                        last_updated: price.last_updated,
+                        quote_denom: price.quote_denom.clone(),
+                        confidence: price.confidence,
+                    }),
+                    None => query_err("No oracle price exists"),
+                },
+                Ok(QueryMsg::CollateralPrice { asset, timeframe }) => {
+                    match self.collaterals.get(&asset) {
+                        Some(info) if self.expired(info.last_updated, timeframe) => {
+                            query_err("Collateral price is too old")
+                        }
+                        Some(info) => query_ok(&CollateralPriceResponse {
+                            asset,
+                            rate: info.rate,
+                            last_updated: info.last_updated,
+                            multiplier: info.multiplier,
+                            is_revoked: info.is_revoked,
+                            confidence: info.confidence,
+                        }),
+                        None => query_err("Collateral info does not exist"),
+                    }
+                }
+                Ok(QueryMsg::CollateralAssetInfo { asset }) => {
+                    self.collateral_info_queries
+                        .set(self.collateral_info_queries.get() + 1);
+                    match self.collaterals.get(&asset) {
+                        Some(info) => query_ok(&CollateralInfoResponse {
+                            asset,
+                            multiplier: info.multiplier,
+                            source_type: "fixed_price".to_string(),
+                            is_revoked: info.is_revoked,
+                            max_deposit: info.max_deposit,
+                            decimals: info.decimals,
+                            price_expire_time: info.price_expire_time,
+                        }),
+                        None => query_err("Collateral info does not exist"),
+                    }
+                }
+                Ok(QueryMsg::TokenInfo {}) => match self.token_decimals.get(contract_addr) {
+                    Some(decimals) => query_ok(&TokenInfoResponse {
+                        name: "mAPPL".to_string(),
+                        symbol: "mAPPL".to_string(),
+                        decimals: *decimals,
+                        total_supply: Uint128::zero(),
+                    }),
+                    None => query_err("No token info exists"),
+                },
+                Ok(QueryMsg::Balance { address }) => {
+                    let balance: Uint128 = self
+                        .token_balances
+                        .get(contract_addr)
+                        .and_then(|balances| balances.get(&address))
+                        .copied()
+                        .unwrap_or_default();
+                    query_ok(&Cw20BalanceResponse { balance })
+                }
+                Ok(QueryMsg::Pair {}) => match self.pairs.get(contract_addr) {
+                    Some(asset_infos) => query_ok(&PairInfo {
+                        asset_infos: asset_infos.clone(),
+                        contract_addr: contract_addr.to_string(),
+                        liquidity_token: "liquidity0000".to_string(),
+                    }),
+                    None => query_err("No pair exists"),
+                },
+                Err(_) => query_err("Query msg not supported"),
+            },
+            _ => self.base.handle_query(request),
+        }
+    }
+
+    fn expired(&self, last_updated: u64, timeframe: Option<u64>) -> bool {
+        matches!(timeframe, Some(timeframe) if last_updated + timeframe < self.time)
+    }
+}
+
+fn query_ok<T: Serialize>(response: &T) -> QuerierResult {
+    SystemResult::Ok(ContractResult::Ok(to_json_binary(response).unwrap()))
+}
+
+fn query_err(error: &str) -> QuerierResult {
+    SystemResult::Ok(ContractResult::Err(error.to_string()))
+}
+
+impl WasmMockQuerier {
+    pub fn new(base: MockQuerier<Empty>) -> Self {
+        WasmMockQuerier {
+            base,
+            oracle_prices: HashMap::new(),
+            collaterals: HashMap::new(),
+            token_decimals: HashMap::new(),
+            token_balances: HashMap::new(),
+            pairs: HashMap::new(),
+            time: MOCK_PRICE_TIME,
+            collateral_info_queries: Cell::new(0),
+        }
+    }
+
+    /// price of the asset in base denom
+    pub fn with_oracle_price(&mut self, asset: &str, rate: Decimal) {
+        self.with_quoted_price(asset, rate, None);
+    }
+
+    /// price of the asset in the quote denom, base denom if not given
+    pub fn with_quoted_price(&mut self, asset: &str, rate: Decimal, quote_denom: Option<&str>) {
+        self.oracle_prices.insert(
+            asset.to_string(),
+            OraclePrice {
+                rate,
+                confidence: Decimal::zero(),
+                quote_denom: quote_denom.map(|denom| denom.to_string()),
+                last_updated: MOCK_PRICE_TIME,
+            },
+        );
+    }
+
+    pub fn with_price_confidence(&mut self, asset: &str, confidence: Decimal) {
+        if let Some(price) = self.oracle_prices.get_mut(asset) {
+            price.confidence = confidence;
+        }
+        if let Some(info) = self.collaterals.get_mut(asset) {
+            info.confidence = confidence;
+        }
+    }
+
+    pub fn with_price_updated(&mut self, asset: &str, last_updated: u64) {
+        if let Some(price) = self.oracle_prices.get_mut(asset) {
+            price.last_updated = last_updated;
+        }
+        if let Some(info) = self.collaterals.get_mut(asset) {
+            info.last_updated = last_updated;
+        }
+    }
+
+    /// the asset can no longer be priced by either oracle
+    pub fn remove_price(&mut self, asset: &str) {
+        self.oracle_prices.remove(asset);
+        self.collaterals.remove(asset);
+    }
+
+    pub fn with_collateral(&mut self, asset: &str, info: CollateralInfo) {
+        self.collaterals.insert(asset.to_string(), info);
+    }
+
+    pub fn collateral_mut(&mut self, asset: &str) -> &mut CollateralInfo {
+        self.collaterals.get_mut(asset).unwrap()
+    }
+
+    /// bank balance of the address, replacing the previous one
+    pub fn with_balance(&mut self, address: &str, balance: &[Coin]) {
+        self.base.update_balance(address, balance.to_vec());
+    }
+
+    pub fn with_token(&mut self, contract_addr: &str, decimals: u8) {
+        self.token_decimals
+            .insert(contract_addr.to_string(), decimals);
+    }
+
+    pub fn with_token_balance(&mut self, contract_addr: &str, address: &str, balance: Uint128) {
+        self.token_balances
+            .entry(contract_addr.to_string())
+            .or_default()
+            .insert(address.to_string(), balance);
+    }
+
+    pub fn with_pair(&mut self, pair: &str, asset_infos: [AssetInfo; 2]) {
+        self.pairs.insert(pair.to_string(), asset_infos);
+    }
+
+    pub fn collateral_info_queries(&self) -> u64 {
+        self.collateral_info_queries.get()
+    }
+}
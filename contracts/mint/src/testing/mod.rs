@@ -0,0 +1,108 @@
+mod contract_test;
+mod migration_test;
+mod mock_querier;
+mod positions_test;
+mod querier_test;
+
+use crate::contract::execute;
+use crate::state::{store_config, store_position_idx, Config};
+use crate::testing::mock_querier::{mock_dependencies, WasmMockQuerier};
+use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage};
+use cosmwasm_std::{Api, Decimal, Env, OwnedDeps, Response, Uint128};
+use melange_protocol::asset::{Asset, AssetInfo};
+use melange_protocol::mint::{ExecuteMsg, FeeSchedule};
+use sei_cosmwasm::SeiMsg;
+
+pub const OWNER: &str = "owner0000";
+pub const ORACLE: &str = "oracle0000";
+pub const COLLATERAL_ORACLE: &str = "collateraloracle0000";
+pub const COLLECTOR: &str = "collector0000";
+pub const BASE_DENOM: &str = "usei";
+pub const ASSET: &str = "asset0000";
+pub const USER: &str = "addr0000";
+
+pub type MockDeps = OwnedDeps<MockStorage, MockApi, WasmMockQuerier>;
+
+/// contract with its config stored directly, as instantiate takes the sei querier
+pub fn setup() -> MockDeps {
+    let mut deps = mock_dependencies(&[]);
+    let api = deps.api;
+    let config = Config {
+        owner: api.addr_canonicalize(OWNER).unwrap(),
+        oracle: api.addr_canonicalize(ORACLE).unwrap(),
+        collector: api.addr_canonicalize(COLLECTOR).unwrap(),
+        collateral_oracle: api.addr_canonicalize(COLLATERAL_ORACLE).unwrap(),
+        staking: api.addr_canonicalize("staking0000").unwrap(),
+        melange_factory: api.addr_canonicalize("factory0000").unwrap(),
+        lock: api.addr_canonicalize("lock0000").unwrap(),
+        base_denom: BASE_DENOM.to_string(),
+        base_decimals: 6u8,
+        token_code_id: 10u64,
+        protocol_fee_rate: Decimal::percent(1),
+        guardian: None,
+        timelock_period: 86400u64,
+        revoked_collateral_grace_period: 604800u64,
+        revoked_collateral_ratio: Decimal::percent(200),
+    };
+    store_config(&mut deps.storage, &config).unwrap();
+    store_position_idx(&mut deps.storage, Uint128::from(1u128)).unwrap();
+
+    deps
+}
+
+/// register the cw20 asset without fees, priced at `price` base denom
+pub fn register_asset(deps: &mut MockDeps, asset: &str, decimals: u8, price: Decimal) {
+    deps.querier.with_token(asset, decimals);
+    deps.querier.with_oracle_price(asset, price);
+
+    let msg = ExecuteMsg::RegisterAsset {
+        asset_token: asset.to_string(),
+        auction_discount: Decimal::percent(20),
+        min_collateral_ratio: Decimal::percent(150),
+        stability_fee_rate: None,
+        fee_schedule: Some(FeeSchedule::default()),
+        debt_ceiling: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+}
+
+/// open a position of USER minting ASSET against the native collateral
+pub fn open_position(
+    deps: &mut MockDeps,
+    env: Env,
+    collateral_denom: &str,
+    amount: u128,
+    collateral_ratio: Decimal,
+) -> Uint128 {
+    let msg = open_position_msg(collateral_denom, amount, collateral_ratio);
+    let res = execute(deps.as_mut(), env, mock_info(USER, &[]), msg).unwrap();
+
+    Uint128::from(attribute(&res, "position_idx").parse::<u128>().unwrap())
+}
+
+pub fn open_position_msg(
+    collateral_denom: &str,
+    amount: u128,
+    collateral_ratio: Decimal,
+) -> ExecuteMsg {
+    ExecuteMsg::OpenPosition {
+        collateral: Asset {
+            info: AssetInfo::NativeToken {
+                denom: collateral_denom.to_string(),
+            },
+            amount: Uint128::from(amount),
+        },
+        asset_info: AssetInfo::Token {
+            contract_addr: ASSET.to_string(),
+        },
+        collateral_ratio,
+    }
+}
+
+pub fn attribute(res: &Response<SeiMsg>, key: &str) -> String {
+    res.attributes
+        .iter()
+        .find(|attr| attr.key == key)
+        .map(|attr| attr.value.clone())
+        .unwrap()
+}
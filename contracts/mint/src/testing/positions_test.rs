@@ -0,0 +1,1722 @@
+use crate::contract::{execute, query, reply, DELEVERAGE_REPLY_ID, LEVERAGE_REPLY_ID};
+use crate::error::ContractError;
+use crate::positions::{accrue_fee_index, compute_fee_index, compute_stability_fee};
+use crate::state::Debt;
+use crate::testing::mock_querier::{CollateralInfo, MOCK_PRICE_TIME};
+use crate::testing::{
+    attribute, open_position, open_position_msg, register_asset, setup, MockDeps, ASSET,
+    BASE_DENOM, COLLECTOR, OWNER, USER,
+};
+use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    coin, from_json, to_json_binary, BankMsg, Coin, CosmosMsg, Decimal, Reply, SubMsg,
+    SubMsgResponse, SubMsgResult, Uint128,
+};
+use cw20::Cw20ReceiveMsg;
+use melange_protocol::asset::{Asset, AssetInfo, AssetInfoRaw};
+use melange_protocol::common::OrderBy;
+use melange_protocol::mint::{
+    Cw20HookMsg, DebtCeilingResponse, DepositCapResponse, ExecuteMsg, FeeSchedule, MarketHours,
+    MarketWindow, NextPositionIdxResponse, Operation, OperatorApprovalResponse,
+    OperatorApprovalsResponse, PositionResponse, PositionsResponse, QueryMsg,
+    RevokedCollateralPositionsResponse, RevokedCollateralResponse, RevokedCollateralsResponse,
+};
+use sei_cosmwasm::SeiMsg;
+
+const SECONDS_PER_YEAR: u64 = 31_536_000u64;
+
+fn asset_raw() -> AssetInfoRaw {
+    AssetInfo::Token {
+        contract_addr: ASSET.to_string(),
+    }
+    .to_raw(&MockApi::default())
+    .unwrap()
+}
+
+fn asset(amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::Token {
+            contract_addr: ASSET.to_string(),
+        },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn native_asset(denom: &str, amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn query_position(deps: &MockDeps, time: u64, idx: Uint128) -> PositionResponse {
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(time);
+    from_json(query(deps.as_ref(), env, QueryMsg::Position { position_idx: idx }).unwrap()).unwrap()
+}
+
+#[test]
+fn positions_queried_by_owner_and_asset() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    for _ in 0..2 {
+        open_position(
+            &mut deps,
+            mock_env(),
+            BASE_DENOM,
+            1_000_000u128,
+            Decimal::percent(200),
+        );
+    }
+
+    let positions = |owner_addr: Option<&str>, asset_token: Option<&str>, order_by| {
+        let msg = QueryMsg::Positions {
+            owner_addr: owner_addr.map(|owner| owner.to_string()),
+            asset_token: asset_token.map(|asset| asset.to_string()),
+            start_after: None,
+            limit: None,
+            order_by,
+        };
+        let res: PositionsResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        res.positions
+            .into_iter()
+            .map(|position| position.idx.u128())
+            .collect::<Vec<u128>>()
+    };
+    assert_eq!(positions(Some(USER), None, Some(OrderBy::Asc)), vec![1, 2]);
+    assert_eq!(positions(None, Some(ASSET), None), vec![2, 1]);
+    assert_eq!(positions(None, None, Some(OrderBy::Asc)), vec![1, 2]);
+    assert!(positions(Some("addr0001"), None, None).is_empty());
+
+    let res: NextPositionIdxResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::NextPositionIdx {}).unwrap()).unwrap();
+    assert_eq!(res.next_position_idx, Uint128::from(3u128));
+}
+
+#[test]
+fn fee_index_accrues_on_elapsed_time() {
+    let mut storage = MockStorage::default();
+    let asset_info = asset_raw();
+    let rate = Decimal::percent(10);
+
+    assert_eq!(
+        accrue_fee_index(&mut storage, &asset_info, rate, 1000u64).unwrap(),
+        Decimal::one()
+    );
+    assert_eq!(
+        compute_fee_index(&storage, &asset_info, rate, 1000u64 + SECONDS_PER_YEAR / 2).unwrap(),
+        Decimal::percent(105)
+    );
+    assert_eq!(
+        accrue_fee_index(&mut storage, &asset_info, rate, 1000u64 + SECONDS_PER_YEAR).unwrap(),
+        Decimal::percent(110)
+    );
+
+    let debt = Debt {
+        info: asset_info,
+        amount: Uint128::from(1000u128),
+        fee_index: Decimal::one(),
+    };
+    assert_eq!(
+        compute_stability_fee(&debt, Decimal::percent(110)),
+        Uint128::from(100u128)
+    );
+}
+
+#[test]
+fn fee_index_checkpointed_once_per_block() {
+    let mut storage = MockStorage::default();
+    let asset_info = asset_raw();
+    let rate = Decimal::percent(10);
+
+    accrue_fee_index(&mut storage, &asset_info, rate, 1000u64).unwrap();
+    let now: u64 = 1000u64 + SECONDS_PER_YEAR;
+    let index = accrue_fee_index(&mut storage, &asset_info, rate, now).unwrap();
+
+    // touching the asset again in the same block does not compound the fee
+    for _ in 0..3 {
+        assert_eq!(
+            accrue_fee_index(&mut storage, &asset_info, rate, now).unwrap(),
+            index
+        );
+    }
+    assert_eq!(
+        compute_fee_index(&storage, &asset_info, rate, now).unwrap(),
+        index
+    );
+}
+
+#[test]
+fn stability_fee_settled_on_deposit() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: Some(Decimal::percent(10)),
+        fee_schedule: None,
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let res = query_position(&deps, SECONDS_PER_YEAR, idx);
+    assert_eq!(res.debt, vec![asset(500_000u128)]);
+    assert_eq!(res.stability_fee, vec![asset(50_000u128)]);
+
+    // the fee is taken from the collateral and sent to the collector
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(SECONDS_PER_YEAR);
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset(BASE_DENOM, 100u128),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(USER, &[coin(100u128, BASE_DENOM)]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: COLLECTOR.to_string(),
+            amount: vec![Coin {
+                denom: BASE_DENOM.to_string(),
+                amount: Uint128::from(50_000u128),
+            }],
+        }))]
+    );
+
+    // a second deposit in the same block settles nothing more
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info(USER, &[coin(100u128, BASE_DENOM)]),
+        msg,
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+
+    let res = query_position(&deps, SECONDS_PER_YEAR, idx);
+    assert_eq!(res.collateral, vec![native_asset(BASE_DENOM, 950_200u128)]);
+    assert_eq!(res.stability_fee, vec![asset(0u128)]);
+}
+
+#[test]
+fn fee_schedule_charged_per_operation() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: Some(FeeSchedule {
+            open_fee_rate: Decimal::percent(1),
+            mint_fee_rate: Decimal::percent(2),
+            burn_fee_rate: Decimal::percent(3),
+            ..FeeSchedule::default()
+        }),
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    // open fee on the 500_000 to be minted
+    let msg = ExecuteMsg::OpenPosition {
+        collateral: native_asset(BASE_DENOM, 1_000_000u128),
+        asset_info: AssetInfo::Token {
+            contract_addr: ASSET.to_string(),
+        },
+        collateral_ratio: Decimal::percent(200),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    assert_eq!(attribute(&res, "open_fee"), "5000usei");
+    assert_eq!(attribute(&res, "mint_amount"), "497500asset0000");
+    let idx = Uint128::from(attribute(&res, "position_idx").parse::<u128>().unwrap());
+
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: asset(100_000u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    assert_eq!(attribute(&res, "mint_fee"), "2000usei");
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: USER.to_string(),
+        amount: Uint128::from(100_000u128),
+        msg: to_json_binary(&Cw20HookMsg::Burn { position_idx: idx }).unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap();
+    assert_eq!(attribute(&res, "burn_fee"), "3000usei");
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.collateral, vec![native_asset(BASE_DENOM, 990_000u128)]);
+    assert_eq!(res.debt, vec![asset(497_500u128)]);
+}
+
+#[test]
+fn debt_ceiling_caps_total_minted() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: Some(Uint128::from(600_000u128)),
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    // a second position would take the total over the ceiling
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DebtCeilingExceeded {
+            total_minted: Uint128::from(1_000_000u128),
+            debt_ceiling: Uint128::from(600_000u128),
+        }
+    );
+
+    // and so would minting more against the first one
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: asset(100_001u128),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DebtCeilingExceeded {
+            total_minted: Uint128::from(600_001u128),
+            debt_ceiling: Uint128::from(600_000u128),
+        }
+    );
+
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: asset(100_000u128),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    let res: DebtCeilingResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::DebtCeiling {
+                asset_token: ASSET.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.debt_ceiling, Some(Uint128::from(600_000u128)));
+    assert_eq!(res.total_minted, Uint128::from(600_000u128));
+}
+
+#[test]
+fn deposit_cap_limits_total_deposited() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo {
+            max_deposit: Some(Uint128::from(1_000_000u128)),
+            ..CollateralInfo::new(Decimal::one())
+        },
+    );
+
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        "uatom",
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset("uatom", 1u128),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[coin(1u128, "uatom")]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::DepositCapExceeded {
+            total_deposited: Uint128::from(1_000_001u128),
+            max_deposit: Uint128::from(1_000_000u128),
+        }
+    );
+
+    let collateral = AssetInfo::NativeToken {
+        denom: "uatom".to_string(),
+    };
+    let res: DepositCapResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::DepositCap {
+                collateral: collateral.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        DepositCapResponse {
+            collateral,
+            max_deposit: Some(Uint128::from(1_000_000u128)),
+            total_deposited: Uint128::from(1_000_000u128),
+        }
+    );
+
+    // the base collateral is never capped
+    let msg = open_position_msg(BASE_DENOM, 10_000_000u128, Decimal::percent(200));
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+}
+
+#[test]
+fn position_operations_typed_errors() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(140));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::LowCollateralRatio {
+            required: Decimal::percent(150),
+            actual: Decimal::percent(140),
+        }
+    );
+
+    let msg = open_position_msg(BASE_DENOM, 0u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::WrongCollateral {});
+
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let withdraw = |amount: u128| ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset(BASE_DENOM, amount)),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        withdraw(2_000_000u128),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InsufficientCollateral {
+            requested: Uint128::from(2_000_000u128),
+            available: Uint128::from(1_000_000u128),
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        withdraw(300_000u128),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::LowCollateralRatio {
+            required: Decimal::percent(150),
+            actual: Decimal::percent(140),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Collateral ratio 1.4 is lower than the required 1.5"
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        withdraw(1u128),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let burn = |amount: u128, msg: Cw20HookMsg| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: USER.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_json_binary(&msg).unwrap(),
+        })
+    };
+    let msg = burn(600_000u128, Cw20HookMsg::Burn { position_idx: idx });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InsufficientDebt {
+            requested: Uint128::from(600_000u128),
+            available: Uint128::from(500_000u128),
+        }
+    );
+
+    // a token the position did not mint
+    let msg = burn(1u128, Cw20HookMsg::Burn { position_idx: idx });
+    let err = execute(deps.as_mut(), mock_env(), mock_info("asset0001", &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::WrongAsset {});
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: USER.to_string(),
+        amount: Uint128::from(1u128),
+        msg: to_json_binary(&QueryMsg::Position { position_idx: idx }).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::InvalidHookMessage {});
+}
+
+#[test]
+fn native_asset_minted_through_token_factory() {
+    let mut deps = setup();
+    let denom: String = format!("factory/{}/mtsla", MOCK_CONTRACT_ADDR);
+    deps.querier.with_oracle_price(&denom, Decimal::one());
+
+    let msg = ExecuteMsg::RegisterNativeAsset {
+        subdenom: "mtsla".to_string(),
+        decimals: 6u8,
+        auction_discount: Decimal::percent(20),
+        min_collateral_ratio: Decimal::percent(150),
+        stability_fee_rate: None,
+        fee_schedule: Some(FeeSchedule::default()),
+        debt_ceiling: None,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Custom(SeiMsg::CreateDenom {
+            subdenom: "mtsla".to_string(),
+        }))]
+    );
+
+    // minted to the contract, then sent on to the owner
+    let msg = ExecuteMsg::OpenPosition {
+        collateral: native_asset(BASE_DENOM, 1_000_000u128),
+        asset_info: AssetInfo::NativeToken {
+            denom: denom.clone(),
+        },
+        collateral_ratio: Decimal::percent(200),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    let idx = Uint128::from(attribute(&res, "position_idx").parse::<u128>().unwrap());
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Custom(SeiMsg::MintTokens {
+                amount: coin(500_000u128, &denom),
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.to_string(),
+                amount: vec![coin(500_000u128, &denom)],
+            })),
+        ]
+    );
+
+    // burned straight from the funds sent along
+    let msg = ExecuteMsg::Burn {
+        position_idx: idx,
+        asset: native_asset(&denom, 100_000u128),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[coin(100_000u128, &denom)]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Custom(SeiMsg::BurnTokens {
+            amount: coin(100_000u128, &denom),
+        }))]
+    );
+
+    // cw20 assets are only burned through the token's send hook
+    let msg = ExecuteMsg::Burn {
+        position_idx: idx,
+        asset: asset(100_000u128),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.debt, vec![native_asset(&denom, 400_000u128)]);
+}
+
+#[test]
+fn collateral_listing_queried_once_with_its_decimals() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo {
+            decimals: 8u8,
+            ..CollateralInfo::new(Decimal::percent(1000))
+        },
+    );
+
+    // 1 ATOM worth 10 SEI mints 5 of the asset at 200%
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        "uatom",
+        100_000_000u128,
+        Decimal::percent(200),
+    );
+    assert_eq!(deps.querier.collateral_info_queries(), 1u64);
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.debt, vec![asset(5_000_000u128)]);
+
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset("uatom", 100u128),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[coin(100u128, "uatom")]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(deps.querier.collateral_info_queries(), 2u64);
+}
+
+#[test]
+fn position_transferred_to_new_owner() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let msg = ExecuteMsg::TransferPosition {
+        position_idx: idx,
+        recipient: "addr0001".to_string(),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    assert_eq!(attribute(&res, "from"), USER);
+    assert_eq!(attribute(&res, "to"), "addr0001");
+    assert_eq!(query_position(&deps, 0u64, idx).owner, "addr0001");
+
+    // only the new owner operates the position
+    let withdraw = ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset(BASE_DENOM, 100u128)),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        withdraw.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        withdraw,
+    )
+    .unwrap();
+}
+
+#[test]
+fn operator_approvals_scoped_and_expiring() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+    let expires: u64 = mock_env().block.time.seconds() + 100u64;
+
+    let msg = ExecuteMsg::ApproveOperator {
+        operator: "operator0000".to_string(),
+        position_idx: Some(idx),
+        operations: Some(vec![Operation::Deposit]),
+        expires: Some(expires),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    let res: OperatorApprovalsResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::OperatorApprovals {
+                owner: USER.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.approvals,
+        vec![OperatorApprovalResponse {
+            operator: "operator0000".to_string(),
+            position_idx: Some(idx),
+            operations: Some(vec![Operation::Deposit]),
+            expires: Some(expires),
+        }]
+    );
+
+    let deposit = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset(BASE_DENOM, 100u128),
+    };
+    let operator_info = mock_info("operator0000", &[coin(100u128, BASE_DENOM)]);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        operator_info.clone(),
+        deposit.clone(),
+    )
+    .unwrap();
+
+    // operations outside the scope are not allowed
+    let withdraw = ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset(BASE_DENOM, 100u128)),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("operator0000", &[]),
+        withdraw,
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // the approval lapses at its expiry
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(100u64);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        operator_info.clone(),
+        deposit.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // an approval over every position of the owner, revoked again
+    let msg = ExecuteMsg::ApproveOperator {
+        operator: "operator0000".to_string(),
+        position_idx: None,
+        operations: None,
+        expires: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        operator_info.clone(),
+        deposit.clone(),
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::RevokeOperator {
+        operator: "operator0000".to_string(),
+        position_idx: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    let err = execute(deps.as_mut(), env, operator_info, deposit).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn position_approvals_cleared_on_transfer() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let msg = ExecuteMsg::ApproveOperator {
+        operator: "operator0000".to_string(),
+        position_idx: Some(idx),
+        operations: None,
+        expires: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    let msg = ExecuteMsg::TransferPosition {
+        position_idx: idx,
+        recipient: "addr0001".to_string(),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    let res: OperatorApprovalsResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::OperatorApprovals {
+                owner: USER.to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.approvals, vec![]);
+
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset(BASE_DENOM, 100u128),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("operator0000", &[coin(100u128, BASE_DENOM)]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn position_closed_in_one_call() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let close = |amount: u128| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: USER.to_string(),
+            amount: Uint128::from(amount),
+            msg: to_json_binary(&Cw20HookMsg::ClosePosition { position_idx: idx }).unwrap(),
+        })
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(ASSET, &[]),
+        close(400_000u128),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InsufficientRepayment {
+            required: Uint128::from(500_000u128),
+            provided: Uint128::from(400_000u128),
+        }
+    );
+
+    // the debt is burned, the collateral withdrawn and the excess refunded
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(ASSET, &[]),
+        close(600_000u128),
+    )
+    .unwrap();
+    assert_eq!(attribute(&res, "burn_amount"), "500000asset0000");
+    assert_eq!(attribute(&res, "withdraw_amount"), "1000000usei");
+    assert_eq!(attribute(&res, "refund_amount"), "100000asset0000");
+    assert!(query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Position { position_idx: idx }
+    )
+    .is_err());
+}
+
+#[test]
+fn position_with_other_debts_not_closed_by_one_token() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    register_asset(&mut deps, "asset0001", 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0001".to_string(),
+            },
+            amount: Uint128::from(100_000u128),
+        },
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: USER.to_string(),
+        amount: Uint128::from(500_000u128),
+        msg: to_json_binary(&Cw20HookMsg::ClosePosition { position_idx: idx }).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::UnpaidDebt {
+            asset: "asset0001".to_string(),
+        }
+    );
+}
+
+fn reply_msg(id: u64) -> Reply {
+    Reply {
+        id,
+        result: SubMsgResult::Ok(SubMsgResponse {
+            events: vec![],
+            data: None,
+        }),
+    }
+}
+
+/// position of 1_000_000 base denom at 400% with the ASSET/base denom pair listed
+fn setup_leverage() -> (MockDeps, Uint128) {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_pair(
+        "pair0000",
+        [
+            AssetInfo::Token {
+                contract_addr: ASSET.to_string(),
+            },
+            AssetInfo::NativeToken {
+                denom: BASE_DENOM.to_string(),
+            },
+        ],
+    );
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(400),
+    );
+    deps.querier
+        .with_balance(MOCK_CONTRACT_ADDR, &[coin(1_000_000u128, BASE_DENOM)]);
+
+    (deps, idx)
+}
+
+#[test]
+fn leverage_deposits_swapped_collateral() {
+    let (mut deps, idx) = setup_leverage();
+
+    let msg = ExecuteMsg::Leverage {
+        position_idx: idx,
+        mint_amount: asset(250_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(240_000u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap();
+    assert_eq!(res.messages.last().unwrap().id, LEVERAGE_REPLY_ID);
+
+    // the pair can not start another swap before the reply
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::SwapInProgress {});
+
+    // a deposit made during the swap is not taken as bought
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset(BASE_DENOM, 100u128),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[coin(100u128, BASE_DENOM)]),
+        msg,
+    )
+    .unwrap();
+    deps.querier
+        .with_balance(MOCK_CONTRACT_ADDR, &[coin(1_250_100u128, BASE_DENOM)]);
+
+    let res = reply(deps.as_mut(), mock_env(), reply_msg(LEVERAGE_REPLY_ID)).unwrap();
+    assert_eq!(attribute(&res, "deposit_amount"), "250000usei");
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(
+        res.collateral,
+        vec![native_asset(BASE_DENOM, 1_250_100u128)]
+    );
+    assert_eq!(res.debt, vec![asset(500_000u128)]);
+}
+
+#[test]
+fn leverage_reverted_below_min_out() {
+    let (mut deps, idx) = setup_leverage();
+
+    let msg = ExecuteMsg::Leverage {
+        position_idx: idx,
+        mint_amount: asset(250_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(240_000u128),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    deps.querier
+        .with_balance(MOCK_CONTRACT_ADDR, &[coin(1_200_000u128, BASE_DENOM)]);
+
+    let err = reply(deps.as_mut(), mock_env(), reply_msg(LEVERAGE_REPLY_ID)).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InsufficientSwapOutput {
+            min_out: Uint128::from(240_000u128),
+            received: Uint128::from(200_000u128),
+        }
+    );
+}
+
+#[test]
+fn deleverage_burns_swapped_asset() {
+    let (mut deps, idx) = setup_leverage();
+
+    let msg = ExecuteMsg::Deleverage {
+        position_idx: idx,
+        collateral_amount: native_asset(BASE_DENOM, 200_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(190_000u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    assert_eq!(res.messages.last().unwrap().id, DELEVERAGE_REPLY_ID);
+    deps.querier
+        .with_token_balance(ASSET, MOCK_CONTRACT_ADDR, Uint128::from(200_000u128));
+
+    let res = reply(deps.as_mut(), mock_env(), reply_msg(DELEVERAGE_REPLY_ID)).unwrap();
+    assert_eq!(attribute(&res, "burn_amount"), "200000asset0000");
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.collateral, vec![native_asset(BASE_DENOM, 800_000u128)]);
+    assert_eq!(res.debt, vec![asset(50_000u128)]);
+}
+
+/// 625_000 minted at 160%, below the minimum once the price rose to 1.1
+fn setup_self_liquidation() -> (MockDeps, Uint128) {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: Some(FeeSchedule {
+            liquidation_fee_rate: Decimal::percent(2),
+            self_liquidation_fee_rate: Decimal::percent(1),
+            ..FeeSchedule::default()
+        }),
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: None,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    deps.querier.with_pair(
+        "pair0000",
+        [
+            AssetInfo::Token {
+                contract_addr: ASSET.to_string(),
+            },
+            AssetInfo::NativeToken {
+                denom: BASE_DENOM.to_string(),
+            },
+        ],
+    );
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(160),
+    );
+    deps.querier
+        .with_oracle_price(ASSET, Decimal::from_ratio(11u128, 10u128));
+
+    (deps, idx)
+}
+
+#[test]
+fn self_liquidation_only_has_to_improve_ratio() {
+    let (mut deps, idx) = setup_self_liquidation();
+
+    let msg = ExecuteMsg::SelfLiquidate {
+        position_idx: idx,
+        collateral_amount: native_asset(BASE_DENOM, 10_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(9_000u128),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    deps.querier
+        .with_token_balance(ASSET, MOCK_CONTRACT_ADDR, Uint128::from(9_000u128));
+
+    // the self liquidation fee is charged on the 9_900 burned value
+    let res = reply(deps.as_mut(), mock_env(), reply_msg(DELEVERAGE_REPLY_ID)).unwrap();
+    assert_eq!(attribute(&res, "action"), "self_liquidate_burn");
+    assert_eq!(attribute(&res, "burn_amount"), "9000asset0000");
+    assert_eq!(attribute(&res, "burn_fee"), "99usei");
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.collateral, vec![native_asset(BASE_DENOM, 989_901u128)]);
+    assert_eq!(res.debt, vec![asset(616_000u128)]);
+}
+
+#[test]
+fn self_liquidation_rejected_for_healthy_positions() {
+    let (mut deps, idx) = setup_self_liquidation();
+    deps.querier
+        .with_oracle_price(ASSET, Decimal::from_ratio(9u128, 10u128));
+
+    // the position is back at 177%, above 150% * 1.1
+    let msg = ExecuteMsg::SelfLiquidate {
+        position_idx: idx,
+        collateral_amount: native_asset(BASE_DENOM, 10_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(9_000u128),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert!(matches!(
+        err,
+        ContractError::SafelyCollateralized { required, .. } if required == Decimal::percent(165)
+    ));
+}
+
+#[test]
+fn deleverage_has_to_reach_min_ratio() {
+    let (mut deps, idx) = setup_self_liquidation();
+
+    let msg = ExecuteMsg::Deleverage {
+        position_idx: idx,
+        collateral_amount: native_asset(BASE_DENOM, 10_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(9_000u128),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+    deps.querier
+        .with_token_balance(ASSET, MOCK_CONTRACT_ADDR, Uint128::from(9_000u128));
+
+    let err = reply(deps.as_mut(), mock_env(), reply_msg(DELEVERAGE_REPLY_ID)).unwrap_err();
+    assert!(matches!(err, ContractError::LowCollateralRatio { .. }));
+}
+
+#[test]
+fn collateral_of_position_swapped() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo::new(Decimal::from_ratio(10u128, 1u128)),
+    );
+    deps.querier.with_collateral(
+        "uluna",
+        CollateralInfo {
+            is_revoked: true,
+            ..CollateralInfo::new(Decimal::one())
+        },
+    );
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+
+    let swap = |new_collateral: Asset| {
+        let info = mock_info(
+            USER,
+            &[coin(
+                new_collateral.amount.u128(),
+                new_collateral.info.to_string(),
+            )],
+        );
+        let msg = ExecuteMsg::SwapCollateral {
+            position_idx: idx,
+            new_collateral,
+            old_collateral: None,
+        };
+        (info, msg)
+    };
+
+    let (info, msg) = swap(native_asset("uluna", 1_000_000u128));
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(err, ContractError::RevokedCollateral {});
+
+    // 50_000 uatom leaves the position at 100%
+    let (info, msg) = swap(native_asset("uatom", 50_000u128));
+    let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert!(matches!(err, ContractError::LowCollateralRatio { .. }));
+
+    let (info, msg) = swap(native_asset("uatom", 100_000u128));
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: USER.to_string(),
+            amount: vec![coin(1_000_000u128, BASE_DENOM)],
+        }))]
+    );
+
+    let res = query_position(&deps, 0u64, idx);
+    assert_eq!(res.collateral, vec![native_asset("uatom", 100_000u128)]);
+    assert_eq!(res.debt, vec![asset(500_000u128)]);
+}
+
+#[test]
+fn revoked_collateral_auctionable_after_grace_period() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo::new(Decimal::from_ratio(10u128, 1u128)),
+    );
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        "uatom",
+        100_000u128,
+        Decimal::percent(180),
+    );
+    deps.querier.collateral_mut("uatom").is_revoked = true;
+
+    // the grace period starts once a position on the collateral is valued
+    let revoked_at: u64 = mock_env().block.time.seconds();
+    let msg = ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset("uatom", 1u128)),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    let uatom = AssetInfo::NativeToken {
+        denom: "uatom".to_string(),
+    };
+    let res: RevokedCollateralsResponse =
+        from_json(query(deps.as_ref(), mock_env(), QueryMsg::RevokedCollaterals {}).unwrap())
+            .unwrap();
+    assert_eq!(
+        res.collaterals,
+        vec![RevokedCollateralResponse {
+            collateral: uatom.clone(),
+            revoked_at,
+            auctionable_at: revoked_at + 604800u64,
+        }]
+    );
+
+    // a later registration does not restart it
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(1000u64);
+    let msg = ExecuteMsg::RegisterRevokedCollateral {
+        collateral: uatom.clone(),
+    };
+    execute(deps.as_mut(), env, mock_info(OWNER, &[]), msg).unwrap();
+    let res: RevokedCollateralPositionsResponse = from_json(
+        query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::RevokedCollateralPositions {
+                collateral: uatom,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.revoked_collateral.revoked_at, revoked_at);
+    assert_eq!(
+        res.positions
+            .iter()
+            .map(|position| position.idx)
+            .collect::<Vec<Uint128>>(),
+        vec![idx]
+    );
+
+    // at 180% the position is only auctioned at the revoked collateral ratio
+    let auction = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "liquidator0000".to_string(),
+        amount: Uint128::from(1000u128),
+        msg: to_json_binary(&Cw20HookMsg::Auction { position_idx: idx }).unwrap(),
+    });
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(604799u64);
+    let err = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info(ASSET, &[]),
+        auction.clone(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::SafelyCollateralized { .. }));
+
+    env.block.time = env.block.time.plus_seconds(1u64);
+    execute(deps.as_mut(), env, mock_info(ASSET, &[]), auction).unwrap();
+}
+
+fn update_price_guard(deps: &mut MockDeps, max_price_change: Decimal) {
+    let msg = ExecuteMsg::UpdatePriceGuard {
+        asset_token: ASSET.to_string(),
+        max_price_change: Some(max_price_change),
+        window: 3600u64,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+}
+
+#[test]
+fn price_guard_halts_opening_positions() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    update_price_guard(&mut deps, Decimal::percent(10));
+    deps.querier
+        .with_oracle_price(ASSET, Decimal::from_ratio(6u128, 5u128));
+
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let info = mock_info(USER, &[]);
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::PriceMoveExceeded {
+            asset: ASSET.to_string(),
+            max_price_change: Decimal::percent(10),
+            actual: Decimal::percent(20),
+        }
+    );
+
+    // the failed operation records nothing, yet the move is not forgotten
+    // once the window has passed
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(7200u64);
+    let err = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::PriceMoveExceeded { .. }));
+
+    // anyone can trip the breaker, which only the owner or guardian lifts
+    let check = ExecuteMsg::CheckPriceMove {
+        asset_token: ASSET.to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), check).unwrap();
+    assert_eq!(attribute(&res, "tripped"), "true");
+    env.block.time = env.block.time.plus_seconds(7200u64);
+    let err = execute(deps.as_mut(), env, info.clone(), msg.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::CircuitBreakerTripped {
+            asset: ASSET.to_string(),
+        }
+    );
+
+    let acknowledge = ExecuteMsg::AcknowledgePriceMove {
+        asset_token: ASSET.to_string(),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        acknowledge.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(OWNER, &[]),
+        acknowledge,
+    )
+    .unwrap();
+    execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+}
+
+#[test]
+fn price_guard_measures_moves_over_the_window() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    update_price_guard(&mut deps, Decimal::percent(10));
+
+    let msg = ExecuteMsg::UpdatePriceGuard {
+        asset_token: ASSET.to_string(),
+        max_price_change: Some(Decimal::zero()),
+        window: 3600u64,
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::InvalidMaxPriceChange {
+            max_price_change: Decimal::zero(),
+        }
+    );
+
+    // two moves within the window add up past the threshold
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let info = mock_info(USER, &[]);
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(106));
+    execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(112));
+    let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+    assert!(matches!(err, ContractError::PriceMoveExceeded { .. }));
+
+    // a price seen after the window replaces the recorded one
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(3600u64);
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(106));
+    execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(112));
+    execute(deps.as_mut(), env, info, msg).unwrap();
+}
+
+#[test]
+fn price_guard_halts_deleverage() {
+    let (mut deps, idx) = setup_leverage();
+    update_price_guard(&mut deps, Decimal::percent(10));
+    deps.querier
+        .with_oracle_price(ASSET, Decimal::from_ratio(6u128, 5u128));
+
+    let msg = ExecuteMsg::Deleverage {
+        position_idx: idx,
+        collateral_amount: native_asset(BASE_DENOM, 100_000u128),
+        pair: "pair0000".to_string(),
+        min_out: Uint128::from(80_000u128),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert!(matches!(err, ContractError::PriceMoveExceeded { .. }));
+}
+
+fn update_market(
+    deps: &mut MockDeps,
+    price_expire_time: Option<u64>,
+    market_hours: Option<MarketHours>,
+) {
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time,
+        remove_price_expire_time: None,
+        market_hours,
+    };
+    execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+}
+
+#[test]
+fn price_expire_time_set_per_asset_and_collateral() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo {
+            price_expire_time: Some(300u64),
+            ..CollateralInfo::new(Decimal::from_ratio(10u128, 1u128))
+        },
+    );
+    deps.querier
+        .with_price_updated(ASSET, MOCK_PRICE_TIME - 120u64);
+    deps.querier
+        .with_price_updated("uatom", MOCK_PRICE_TIME - 120u64);
+
+    // the asset price is older than the default expiry
+    let msg = open_position_msg("uatom", 100_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert!(err.to_string().contains("Price is too old"));
+
+    // the collateral listing accepts its price for 300 seconds
+    update_market(&mut deps, Some(300u64), None);
+    execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap();
+
+    deps.querier.collateral_mut("uatom").price_expire_time = None;
+    let msg = open_position_msg("uatom", 100_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert!(err.to_string().contains("Collateral price is too old"));
+}
+
+#[test]
+fn market_hours_halt_minting_not_repaying() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(300),
+    );
+
+    // open from monday to wednesday 00:00, closed at the mock_env wednesday
+    update_market(
+        &mut deps,
+        None,
+        Some(MarketHours {
+            windows: vec![MarketWindow {
+                open: 0u64,
+                close: 2u64 * 86400u64,
+            }],
+            holidays: vec![],
+        }),
+    );
+
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: asset(100u128),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::MarketClosed {});
+    let msg = ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset(BASE_DENOM, 100u128)),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::MarketClosed {});
+
+    let msg = ExecuteMsg::Deposit {
+        position_idx: idx,
+        collateral: native_asset(BASE_DENOM, 100u128),
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[coin(100u128, BASE_DENOM)]),
+        msg,
+    )
+    .unwrap();
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: USER.to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_json_binary(&Cw20HookMsg::Burn { position_idx: idx }).unwrap(),
+    });
+    execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap();
+
+    // the market opens again on monday
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(5u64 * 86400u64);
+    let msg = ExecuteMsg::Mint {
+        position_idx: idx,
+        asset: asset(100u128),
+    };
+    execute(deps.as_mut(), env, mock_info(USER, &[]), msg).unwrap();
+}
+
+#[test]
+fn market_closed_on_holidays() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(150),
+    );
+    let midnight: u64 = MOCK_PRICE_TIME - MOCK_PRICE_TIME % 86400u64;
+    let market_hours = |holiday: u64| MarketHours {
+        windows: vec![MarketWindow {
+            open: 0u64,
+            close: 7u64 * 86400u64 - 1u64,
+        }],
+        holidays: vec![holiday],
+    };
+
+    let msg = ExecuteMsg::UpdateAsset {
+        asset_token: ASSET.to_string(),
+        auction_discount: None,
+        min_collateral_ratio: None,
+        stability_fee_rate: None,
+        fee_schedule: None,
+        debt_ceiling: None,
+        remove_debt_ceiling: None,
+        price_expire_time: None,
+        remove_price_expire_time: None,
+        market_hours: Some(market_hours(midnight + 3600u64)),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+    assert_eq!(err, ContractError::InvalidMarketHours {});
+    update_market(&mut deps, None, Some(market_hours(midnight)));
+
+    let msg = open_position_msg(BASE_DENOM, 1_000_000u128, Decimal::percent(200));
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg.clone()).unwrap_err();
+    assert_eq!(err, ContractError::MarketClosed {});
+    let auction = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "liquidator0000".to_string(),
+        amount: Uint128::from(1000u128),
+        msg: to_json_binary(&Cw20HookMsg::Auction { position_idx: idx }).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), auction).unwrap_err();
+    assert_eq!(err, ContractError::MarketClosed {});
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(86400u64);
+    execute(deps.as_mut(), env, mock_info(USER, &[]), msg).unwrap();
+}
+
+#[test]
+fn positions_valued_at_conservative_side_of_band() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        BASE_DENOM,
+        1_000_000u128,
+        Decimal::percent(200),
+    );
+    deps.querier
+        .with_price_confidence(ASSET, Decimal::percent(20));
+
+    // withdrawing prices the debt at the 1.2 high end
+    let withdraw = |amount: u128| ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset(BASE_DENOM, amount)),
+    };
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        withdraw(200_000u128),
+    )
+    .unwrap_err();
+    assert!(matches!(err, ContractError::LowCollateralRatio { .. }));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(USER, &[]),
+        withdraw(100_000u128),
+    )
+    .unwrap();
+
+    // auctions use the mid price, at which the position is still at 180%
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "liquidator0000".to_string(),
+        amount: Uint128::from(1000u128),
+        msg: to_json_binary(&Cw20HookMsg::Auction { position_idx: idx }).unwrap(),
+    });
+    let err = execute(deps.as_mut(), mock_env(), mock_info(ASSET, &[]), msg).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::SafelyCollateralized {
+            required: Decimal::percent(150),
+            actual: Decimal::percent(180),
+        }
+    );
+}
+
+#[test]
+fn asset_priced_through_collateral_its_path_reaches() {
+    let mut deps = setup();
+    register_asset(&mut deps, ASSET, 6u8, Decimal::one());
+    deps.querier.with_collateral(
+        "uatom",
+        CollateralInfo::new(Decimal::from_ratio(10u128, 1u128)),
+    );
+
+    // the oracle quotes the asset in uatom only, priced by the collateral oracle
+    deps.querier
+        .with_quoted_price(ASSET, Decimal::percent(10), Some("uatom"));
+    let idx = open_position(
+        &mut deps,
+        mock_env(),
+        "uatom",
+        100_000u128,
+        Decimal::percent(200),
+    );
+    assert_eq!(
+        query_position(&deps, 0u64, idx).debt,
+        vec![asset(500_000u128)]
+    );
+
+    let msg = ExecuteMsg::Withdraw {
+        position_idx: idx,
+        collateral: Some(native_asset("uatom", 30_000u128)),
+    };
+    let err = execute(deps.as_mut(), mock_env(), mock_info(USER, &[]), msg).unwrap_err();
+    assert!(matches!(err, ContractError::LowCollateralRatio { .. }));
+}
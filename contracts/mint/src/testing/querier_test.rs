@@ -0,0 +1,146 @@
+use crate::querier::{query_collateral_asset_info, query_price, query_price_path, PriceBand};
+use crate::testing::mock_querier::{mock_dependencies, CollateralInfo};
+use crate::testing::{MockDeps, ASSET, BASE_DENOM, COLLATERAL_ORACLE, ORACLE};
+use cosmwasm_std::{from_json, Addr, Decimal, Empty, QuerierWrapper, StdError, StdResult};
+use melange_protocol::collateral_oracle::CollateralInfoResponse;
+
+fn band(low: u64, mid: u64, high: u64) -> PriceBand {
+    PriceBand {
+        low: Decimal::percent(low),
+        mid: Decimal::percent(mid),
+        high: Decimal::percent(high),
+    }
+}
+
+#[test]
+fn price_band_around_confidence() {
+    assert_eq!(
+        PriceBand::new(Decimal::percent(200), Decimal::percent(50)).unwrap(),
+        band(150, 200, 250)
+    );
+    assert_eq!(PriceBand::exact(Decimal::percent(200)), band(200, 200, 200));
+    assert!(PriceBand::new(Decimal::percent(200), Decimal::percent(200)).is_err());
+}
+
+#[test]
+fn price_band_chained_at_widest() {
+    let price = band(150, 200, 250);
+    let quote = band(50, 100, 150);
+
+    assert_eq!(price.multiply(&quote), band(75, 200, 375));
+    assert_eq!(price.divide(&quote), band(100, 200, 500));
+}
+
+fn price_path(deps: &MockDeps, quote_assets: &[&str]) -> StdResult<(PriceBand, String)> {
+    query_price_path(
+        &QuerierWrapper::<Empty>::new(&deps.querier),
+        &Addr::unchecked(ORACLE),
+        BASE_DENOM,
+        ASSET.to_string(),
+        quote_assets,
+        None,
+    )
+}
+
+#[test]
+fn price_path_chains_quote_denoms() {
+    let mut deps = mock_dependencies(&[]);
+
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(200));
+    assert_eq!(
+        price_path(&deps, &[]).unwrap(),
+        (band(200, 200, 200), BASE_DENOM.to_string())
+    );
+
+    // asset in uatom in base denom
+    deps.querier
+        .with_quoted_price(ASSET, Decimal::percent(50), Some("uatom"));
+    deps.querier
+        .with_oracle_price("uatom", Decimal::percent(1000));
+    assert_eq!(
+        price_path(&deps, &[]).unwrap(),
+        (band(500, 500, 500), BASE_DENOM.to_string())
+    );
+    assert_eq!(
+        price_path(&deps, &["uatom"]).unwrap(),
+        (band(50, 50, 50), "uatom".to_string())
+    );
+
+    // asset in ueur in uatom in base denom
+    deps.querier
+        .with_quoted_price(ASSET, Decimal::percent(200), Some("ueur"));
+    deps.querier
+        .with_quoted_price("ueur", Decimal::percent(50), Some("uatom"));
+    assert_eq!(
+        price_path(&deps, &[]).unwrap(),
+        (band(1000, 1000, 1000), BASE_DENOM.to_string())
+    );
+}
+
+#[test]
+fn price_path_limited_to_max_hops() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier
+        .with_quoted_price(ASSET, Decimal::one(), Some("ueur"));
+    deps.querier
+        .with_quoted_price("ueur", Decimal::one(), Some("uusd"));
+    deps.querier
+        .with_quoted_price("uusd", Decimal::one(), Some("uatom"));
+    deps.querier.with_oracle_price("uatom", Decimal::one());
+
+    assert_eq!(
+        price_path(&deps, &[]).unwrap_err(),
+        StdError::generic_err("price path does not reach usei within 3 hops")
+    );
+    assert_eq!(
+        price_path(&deps, &["uatom"]).unwrap(),
+        (band(100, 100, 100), "uatom".to_string())
+    );
+}
+
+#[test]
+fn price_crossed_through_base_denom_off_path() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_oracle_price(ASSET, Decimal::percent(200));
+    deps.querier
+        .with_oracle_price("uatom", Decimal::percent(1000));
+
+    let price: PriceBand = query_price(
+        &QuerierWrapper::<Empty>::new(&deps.querier),
+        Addr::unchecked(ORACLE),
+        BASE_DENOM,
+        ASSET.to_string(),
+        Some("uatom".to_string()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(price, band(20, 20, 20));
+}
+
+#[test]
+fn collateral_decimals_bounded() {
+    let mut deps = mock_dependencies(&[]);
+    let mut collateral = CollateralInfo::new(Decimal::one());
+    collateral.decimals = 19u8;
+    deps.querier.with_collateral("uatom", collateral);
+
+    let err = query_collateral_asset_info(
+        &QuerierWrapper::<Empty>::new(&deps.querier),
+        Addr::unchecked(COLLATERAL_ORACLE),
+        "uatom".to_string(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err("collateral decimals must be at most 18; got 19")
+    );
+}
+
+#[test]
+fn collateral_decimals_default_for_older_oracles() {
+    let res: CollateralInfoResponse = from_json(
+        br#"{"asset":"uatom","multiplier":"1","source_type":"native","is_revoked":false,"max_deposit":null}"#,
+    )
+    .unwrap();
+    assert_eq!(res.decimals, 6u8);
+}
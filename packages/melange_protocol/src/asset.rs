@@ -2,7 +2,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use cosmwasm_std::{
-    to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal, MessageInfo,
+    to_json_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal, MessageInfo,
     QuerierWrapper, StdError, StdResult, SubMsg, Uint128, WasmMsg, AllBalanceResponse,
     BalanceResponse, BankQuery, QueryRequest, WasmQuery,
 };
@@ -38,7 +38,7 @@ pub fn query_token_balance(
 ) -> StdResult<Uint128> {
     let res: Cw20BalanceResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: contract_addr.to_string(),
-        msg: to_binary(&Cw20QueryMsg::Balance {
+        msg: to_json_binary(&Cw20QueryMsg::Balance {
             address: account_addr.to_string(),
         })?,
     }))?;
@@ -51,12 +51,21 @@ pub fn query_supply(querier: &QuerierWrapper, contract_addr: Addr) -> StdResult<
     // load price form the oracle
     let token_info: TokenInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: contract_addr.to_string(),
-        msg: to_binary(&Cw20QueryMsg::TokenInfo {})?,
+        msg: to_json_binary(&Cw20QueryMsg::TokenInfo {})?,
     }))?;
 
     Ok(token_info.total_supply)
 }
 
+pub fn query_token_decimals(querier: &QuerierWrapper, contract_addr: Addr) -> StdResult<u8> {
+    let token_info: TokenInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_addr.to_string(),
+        msg: to_json_binary(&Cw20QueryMsg::TokenInfo {})?,
+    }))?;
+
+    Ok(token_info.decimals)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Asset {
     pub info: AssetInfo,
@@ -75,6 +84,114 @@ impl Asset {
     pub fn is_native_token(&self) -> bool {
         self.info.is_native_token()
     }
+
+    /// Tax included in the amount for a transfer taxed at `tax_rate`, capped at `tax_cap`
+    pub fn compute_tax(&self, tax_rate: Decimal, tax_cap: Uint128) -> StdResult<Uint128> {
+        if let AssetInfo::NativeToken { .. } = &self.info {
+            let amount = self.amount;
+            Ok(std::cmp::min(
+                amount.checked_sub(amount.multiply_ratio(
+                    DECIMAL_FRACTION,
+                    DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
+                ))?,
+                tax_cap,
+            ))
+        } else {
+            Ok(Uint128::zero())
+        }
+    }
+
+    /// Coin to send so the sent amount plus tax equals the asset amount
+    pub fn deduct_tax(&self, tax_rate: Decimal, tax_cap: Uint128) -> StdResult<Coin> {
+        if let AssetInfo::NativeToken { denom } = &self.info {
+            Ok(Coin {
+                denom: denom.to_string(),
+                amount: self.amount.checked_sub(self.compute_tax(tax_rate, tax_cap)?)?,
+            })
+        } else {
+            Err(StdError::generic_err("cannot deduct tax from token asset"))
+        }
+    }
+
+    /// Transfer the asset to the recipient; native tokens are sent untaxed,
+    /// use `into_msg_with_tax` for denoms charging a transfer fee
+    pub fn into_msg<T>(self, recipient: Addr) -> StdResult<CosmosMsg<T>> {
+        self.into_msg_with_tax(recipient, Decimal::zero(), Uint128::zero())
+    }
+
+    /// Transfer the asset to the recipient; for native tokens the tax is
+    /// deducted from the sent amount
+    pub fn into_msg_with_tax<T>(
+        self,
+        recipient: Addr,
+        tax_rate: Decimal,
+        tax_cap: Uint128,
+    ) -> StdResult<CosmosMsg<T>> {
+        match &self.info {
+            AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: self.amount,
+                })?,
+                funds: vec![],
+            })),
+            AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![self.deduct_tax(tax_rate, tax_cap)?],
+            })),
+        }
+    }
+
+    pub fn into_submsg<T>(self, recipient: Addr) -> StdResult<SubMsg<T>> {
+        Ok(SubMsg::new(self.into_msg(recipient)?))
+    }
+
+    /// Pull a cw20 asset from the owner, who has to have approved the allowance
+    pub fn into_transfer_from_msg<T>(self, owner: Addr, recipient: Addr) -> StdResult<CosmosMsg<T>> {
+        match &self.info {
+            AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: owner.to_string(),
+                    recipient: recipient.to_string(),
+                    amount: self.amount,
+                })?,
+                funds: vec![],
+            })),
+            AssetInfo::NativeToken { .. } => Err(StdError::generic_err(
+                "TransferFrom does not apply to native tokens",
+            )),
+        }
+    }
+
+    pub fn checked_add(&self, other: &Asset) -> StdResult<Asset> {
+        self.assert_same_info(other)?;
+        Ok(Asset {
+            info: self.info.clone(),
+            amount: self.amount.checked_add(other.amount)?,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Asset) -> StdResult<Asset> {
+        self.assert_same_info(other)?;
+        Ok(Asset {
+            info: self.info.clone(),
+            amount: self.amount.checked_sub(other.amount)?,
+        })
+    }
+
+    fn assert_same_info(&self, other: &Asset) -> StdResult<()> {
+        if !self.info.equal(&other.info) {
+            return Err(StdError::generic_err(format!(
+                "Asset info mismatch: {} != {}",
+                self.info, other.info
+            )));
+        }
+
+        Ok(())
+    }
+
     pub fn assert_sent_native_token_balance(&self, message_info: &MessageInfo) -> StdResult<()> {
         if let AssetInfo::NativeToken { denom } = &self.info {
             match message_info.funds.iter().find(|x| x.denom == *denom) {
@@ -187,6 +304,19 @@ impl AssetInfoRaw {
 }
 
 impl AssetInfo {
+    /// Resolve an asset given as plain string, the way the oracles reference assets;
+    /// anything that is not a valid address is taken as a native denom
+    pub fn from_string(api: &dyn Api, asset: &str) -> AssetInfo {
+        match api.addr_validate(asset) {
+            Ok(contract_addr) => AssetInfo::Token {
+                contract_addr: contract_addr.to_string(),
+            },
+            Err(_) => AssetInfo::NativeToken {
+                denom: asset.to_string(),
+            },
+        }
+    }
+
     pub fn to_raw(&self, api: &dyn Api) -> StdResult<AssetInfoRaw> {
         match self {
             AssetInfo::NativeToken { denom } => Ok(AssetInfoRaw::NativeToken {
@@ -204,6 +334,24 @@ impl AssetInfo {
             AssetInfo::Token { .. } => false,
         }
     }
+    /// Decimals of the asset; cw20 decimals are read from the token,
+    /// native denoms carry no metadata so theirs have to be configured
+    pub fn query_decimals(
+        &self,
+        querier: &QuerierWrapper,
+        api: &dyn Api,
+        configured: Option<u8>,
+    ) -> StdResult<u8> {
+        match self {
+            AssetInfo::Token { contract_addr } => {
+                query_token_decimals(querier, api.addr_validate(contract_addr.as_str())?)
+            }
+            AssetInfo::NativeToken { denom } => configured.ok_or_else(|| {
+                StdError::generic_err(format!("decimals must be configured for {}", denom))
+            }),
+        }
+    }
+
     pub fn query_pool(
         &self,
         querier: &QuerierWrapper,
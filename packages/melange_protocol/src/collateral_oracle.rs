@@ -1,4 +1,4 @@
-use cosmwasm_std::Decimal;
+use cosmwasm_std::{Decimal, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -24,6 +24,11 @@ pub enum ExecuteMsg {
         asset: AssetInfo,
         price_source: SourceType,
         multiplier: Decimal,
+        max_deposit: Option<Uint128>,
+        /// required for native denoms; queried from the token for cw20 collaterals
+        decimals: Option<u8>,
+        /// oldest price accepted by the mint contract, in seconds
+        price_expire_time: Option<u64>,
     },
     RevokeCollateralAsset {
         asset: AssetInfo,
@@ -36,6 +41,18 @@ pub enum ExecuteMsg {
         asset: AssetInfo,
         multiplier: Decimal,
     },
+    /// Set the maximum amount of the collateral that can be deposited in the mint contract;
+    /// `None` removes the cap
+    UpdateCollateralMaxDeposit {
+        asset: AssetInfo,
+        max_deposit: Option<Uint128>,
+    },
+    /// Set the oldest price of the collateral accepted by the mint contract;
+    /// `None` falls back to the mint default
+    UpdateCollateralPriceExpireTime {
+        asset: AssetInfo,
+        price_expire_time: Option<u64>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -66,6 +83,13 @@ pub struct CollateralPriceResponse {
     pub last_updated: u64,
     pub multiplier: Decimal,
     pub is_revoked: bool,
+    /// half width of the confidence band around `rate`
+    #[serde(default)]
+    pub confidence: Decimal,
+}
+
+fn default_decimals() -> u8 {
+    6
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -74,6 +98,12 @@ pub struct CollateralInfoResponse {
     pub multiplier: Decimal,
     pub source_type: String,
     pub is_revoked: bool,
+    pub max_deposit: Option<Uint128>,
+    /// older oracles do not report decimals; their collateral has 6
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    #[serde(default)]
+    pub price_expire_time: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
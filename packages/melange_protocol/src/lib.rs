@@ -2,3 +2,8 @@ pub mod mint;
 pub mod collateral_oracle;
 pub mod asset;
 pub mod common;
+pub mod pair;
+pub mod oracle;
+
+#[cfg(test)]
+mod testing;
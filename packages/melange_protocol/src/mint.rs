@@ -1,7 +1,8 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-use cosmwasm_std::{Decimal, Uint128};
+use cosmwasm_std::{Binary, Decimal, Uint128};
 use cw20::Cw20ReceiveMsg;
 
 use crate::common::OrderBy;
@@ -10,10 +11,25 @@ use crate::asset::{Asset, AssetInfo};
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub owner: String,
+    pub oracle: String,
+    pub collector: String,
+    pub collateral_oracle: String,
     pub staking: String,
+    pub melange_factory: String,
+    pub lock: String,
     pub base_denom: String,
+    /// decimals of `base_denom`
+    pub base_decimals: u8,
     pub token_code_id: u64,
     pub protocol_fee_rate: Decimal,
+    pub guardian: Option<String>,
+    /// delay in seconds before a queued change can be executed
+    pub timelock_period: u64,
+    /// seconds positions on a revoked collateral have to move to another collateral
+    pub revoked_collateral_grace_period: u64,
+    /// collateral ratio positions on a revoked collateral are auctioned below
+    /// once the grace period is over
+    pub revoked_collateral_ratio: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -21,32 +37,119 @@ pub struct InstantiateMsg {
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
 
-    /// Update config; only owner is allowed to execute it
+    /// Update config; only owner is allowed to execute it.
+    /// Oracles and the protocol fee can only be changed through the timelock
     UpdateConfig {
-        owner: Option<String>,
-        oracle: Option<String>,
         collector: Option<String>,
-        collateral_oracle: Option<String>,
         melange_factory: Option<String>,
         lock: Option<String>,
         token_code_id: Option<u64>,
-        protocol_fee_rate: Option<Decimal>,
         staking: Option<String>,
+        guardian: Option<String>,
+        /// Clear the guardian; takes precedence over `guardian`
+        remove_guardian: Option<bool>,
+        revoked_collateral_grace_period: Option<u64>,
+        revoked_collateral_ratio: Option<Decimal>,
     },
-    /// Update asset related parameters
+    /// Propose a new owner; the change takes effect once claimed
+    ProposeNewOwner {
+        owner: String,
+    },
+    /// Accept the ownership; only the proposed owner is allowed to execute it
+    ClaimOwnership {},
+    /// Withdraw the pending owner proposal; only owner is allowed to execute it
+    CancelOwnershipProposal {},
+    /// Queue a sensitive change that becomes executable after the timelock period
+    QueueChange {
+        change: TimelockedChange,
+    },
+    /// Apply a queued change whose timelock has passed
+    ExecuteChange {
+        id: u64,
+    },
+    CancelChange {
+        id: u64,
+    },
+    /// Update asset related parameters; `asset_token` is the cw20 address or the native denom
     UpdateAsset {
         asset_token: String,
+        auction_discount: Option<Decimal>,
         min_collateral_ratio: Option<Decimal>,
+        stability_fee_rate: Option<Decimal>,
+        fee_schedule: Option<FeeSchedule>,
+        debt_ceiling: Option<Uint128>,
+        /// Clear the debt ceiling; takes precedence over `debt_ceiling`
+        remove_debt_ceiling: Option<bool>,
+        /// oldest oracle price accepted, in seconds
+        price_expire_time: Option<u64>,
+        /// Fall back to the default price expire time; takes precedence
+        /// over `price_expire_time`
+        remove_price_expire_time: Option<bool>,
+        /// trading schedule; empty windows clear it, holidays included,
+        /// and keep the market always open
+        market_hours: Option<MarketHours>,
     },
     /// Generate asset token initialize msg and register required infos except token address
     RegisterAsset {
         asset_token: String,
+        auction_discount: Decimal,
+        min_collateral_ratio: Decimal,
+        stability_fee_rate: Option<Decimal>,
+        /// defaults to `protocol_fee_rate` on burn and liquidation only
+        fee_schedule: Option<FeeSchedule>,
+        /// maximum total amount that can be minted; unlimited if not set
+        debt_ceiling: Option<Uint128>,
+    },
+    /// Create a token factory denom `factory/{contract}/{subdenom}` minted by this contract
+    /// and register it as an asset
+    RegisterNativeAsset {
+        subdenom: String,
+        decimals: u8,
+        auction_discount: Decimal,
         min_collateral_ratio: Decimal,
+        stability_fee_rate: Option<Decimal>,
+        fee_schedule: Option<FeeSchedule>,
+        debt_ceiling: Option<Uint128>,
     },
     RegisterMigration {
         asset_token: String,
         end_price: Decimal,
     },
+    /// Start the grace period of a collateral revoked in the collateral oracle;
+    /// executed by the collateral oracle on revocation, or by the owner
+    RegisterRevokedCollateral {
+        collateral: AssetInfo,
+    },
+    /// Pause operations globally or for a single asset; owner or guardian.
+    /// Pauses every operation if `operations` is not given
+    Pause {
+        asset_token: Option<String>,
+        operations: Option<Vec<Operation>>,
+    },
+    /// Lift a pause globally or for a single asset; only owner.
+    /// Unpauses every operation if `operations` is not given
+    Unpause {
+        asset_token: Option<String>,
+        operations: Option<Vec<Operation>>,
+    },
+    /// Set the circuit breaker of an asset or collateral; only owner.
+    /// Removes the breaker if `max_price_change` is not given
+    UpdatePriceGuard {
+        asset_token: String,
+        max_price_change: Option<Decimal>,
+        /// seconds a recorded price stays the one moves are measured from
+        window: u64,
+    },
+    /// Compare the current price against the recorded one, tripping the
+    /// circuit breaker on a move over the threshold; guarded operations only
+    /// fail on such a move, they can not record the trip
+    CheckPriceMove {
+        asset_token: String,
+    },
+    /// Reset a tripped circuit breaker to the current price; owner or guardian
+    AcknowledgePriceMove {
+        asset_token: String,
+    },
     //////////////////////
     /// User Operations
     //////////////////////
@@ -66,11 +169,85 @@ pub enum ExecuteMsg {
         position_idx: Uint128,
         collateral: Option<Asset>,
     },
+    /// Replace collateral of the position with a native collateral sent along with the message
+    SwapCollateral {
+        position_idx: Uint128,
+        new_collateral: Asset,
+        /// every other collateral of the position is returned if not set
+        old_collateral: Option<Asset>,
+    },
     /// Convert all deposit collateral to asset
     Mint {
         position_idx: Uint128,
         asset: Asset,
     },
+    /// Burn a native asset sent along with the message
+    Burn {
+        position_idx: Uint128,
+        asset: Asset,
+    },
+    /// Buy discounted collateral with a native asset sent along with the message
+    Auction {
+        position_idx: Uint128,
+        asset: Asset,
+    },
+    /// Mint the asset and swap it on the pair into collateral deposited to the position
+    Leverage {
+        position_idx: Uint128,
+        mint_amount: Asset,
+        pair: String,
+        /// minimum amount of collateral the swap has to return
+        min_out: Uint128,
+    },
+    /// Withdraw collateral and swap it on the pair into a minted asset burned from the debt
+    Deleverage {
+        position_idx: Uint128,
+        collateral_amount: Asset,
+        pair: String,
+        /// minimum amount of the asset the swap has to return
+        min_out: Uint128,
+    },
+    /// Mint the asset to the sender and execute `callback` on it; the sender has to
+    /// send the amount plus the flash mint fee back before the callback returns
+    FlashMint {
+        asset_token: String,
+        amount: Uint128,
+        callback: Binary,
+    },
+    /// Sell collateral of a position near liquidation on the pair and burn the bought asset
+    /// against its debt; only the owner or an approved operator is allowed to execute it
+    SelfLiquidate {
+        position_idx: Uint128,
+        collateral_amount: Asset,
+        pair: String,
+        /// minimum amount of the asset the swap has to return
+        min_out: Uint128,
+    },
+    /// Burn the whole debt with the native assets sent along with the message
+    /// and withdraw all the collateral; the position is removed
+    ClosePosition {
+        position_idx: Uint128,
+    },
+    /// Hand the position over to the recipient; only the owner is allowed to execute it
+    TransferPosition {
+        position_idx: Uint128,
+        recipient: String,
+    },
+    /// Allow an operator to deposit, withdraw, mint or burn on behalf of the sender;
+    /// withdrawn collateral and minted assets still go to the owner
+    ApproveOperator {
+        operator: String,
+        /// every position of the sender if not set
+        position_idx: Option<Uint128>,
+        /// every operation if not set
+        operations: Option<Vec<Operation>>,
+        /// block time in seconds after which the approval lapses
+        expires: Option<u64>,
+    },
+    RevokeOperator {
+        operator: String,
+        position_idx: Option<Uint128>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -83,10 +260,35 @@ pub enum Cw20HookMsg {
     },
     /// Deposit more collateral
     Deposit { position_idx: Uint128 },
+    /// Replace collateral of the position with the sent token
+    SwapCollateral {
+        position_idx: Uint128,
+        old_collateral: Option<Asset>,
+    },
     /// Convert specified asset amount and send back to user
     Burn { position_idx: Uint128 },
     /// Buy discounted collateral from the contract with their asset tokens
     Auction { position_idx: Uint128 },
+    /// Burn the whole debt and withdraw all the collateral; only for positions
+    /// whose single debt is the sent token, other debts have to be burned first
+    ClosePosition { position_idx: Uint128 },
+}
+
+/// Moves the stored positions to the collateral basket and debt list layout
+/// and completes the legacy config and asset configs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// auction discount of the assets registered without one
+    pub auction_discount: Decimal,
+    /// decimals of `base_denom`
+    pub base_decimals: u8,
+    /// delay in seconds before a queued change can be executed
+    pub timelock_period: u64,
+    /// seconds positions on a revoked collateral have to move to another collateral
+    pub revoked_collateral_grace_period: u64,
+    /// collateral ratio positions on a revoked collateral are auctioned below
+    /// once the grace period is over
+    pub revoked_collateral_ratio: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -107,6 +309,40 @@ pub enum QueryMsg {
         order_by: Option<OrderBy>,
     },
     NextPositionIdx {},
+    AssetStats {
+        asset_token: String,
+    },
+    ProtocolStats {},
+    FeeSchedule {
+        asset_token: String,
+    },
+    DebtCeiling {
+        asset_token: String,
+    },
+    DepositCap {
+        collateral: AssetInfo,
+    },
+    PauseStatus {
+        asset_token: Option<String>,
+    },
+    PendingOwner {},
+    QueuedChanges {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    OperatorApprovals {
+        owner: String,
+    },
+    RevokedCollaterals {},
+    /// Positions holding the revoked collateral
+    RevokedCollateralPositions {
+        collateral: AssetInfo,
+        start_after: Option<Uint128>,
+        limit: Option<u32>,
+    },
+    PriceGuard {
+        asset_token: String,
+    },
 }
 
 // We define a custom struct for each query response
@@ -120,22 +356,283 @@ pub struct ConfigResponse {
     pub melange_factory: String,
     pub lock: String,
     pub base_denom: String,
+    pub base_decimals: u8,
     pub token_code_id: u64,
     pub protocol_fee_rate: Decimal,
+    pub guardian: Option<String>,
+    pub timelock_period: u64,
+    pub revoked_collateral_grace_period: u64,
+    pub revoked_collateral_ratio: Decimal,
 }
 
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AssetConfigResponse {
     pub token: String,
+    pub decimals: u8,
+    pub auction_discount: Decimal,
     pub min_collateral_ratio: Decimal,
     pub end_price: Option<Decimal>,
+    pub stability_fee_rate: Decimal,
+    pub fee_schedule: FeeSchedule,
+    pub debt_ceiling: Option<Uint128>,
+    pub price_expire_time: u64,
+    pub market_hours: Option<MarketHours>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct PositionResponse {
     pub idx: Uint128,
     pub owner: String,
-    pub collateral: Asset,
-    pub asset: Asset,
+    pub collateral: Vec<Asset>,
+    pub debt: Vec<Asset>,
+    /// stability fee accrued on each debt since the position was last touched
+    pub stability_fee: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionsResponse {
+    pub positions: Vec<PositionResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NextPositionIdxResponse {
+    pub next_position_idx: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RevokedCollateralResponse {
+    pub collateral: AssetInfo,
+    pub revoked_at: u64,
+    /// positions still holding the collateral can be auctioned
+    /// at `revoked_collateral_ratio` from this time
+    pub auctionable_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceGuardResponse {
+    pub asset_token: String,
+    pub max_price_change: Decimal,
+    pub window: u64,
+    pub last_price: Option<Decimal>,
+    pub last_updated: u64,
+    pub tripped: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RevokedCollateralsResponse {
+    pub collaterals: Vec<RevokedCollateralResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RevokedCollateralPositionsResponse {
+    pub revoked_collateral: RevokedCollateralResponse,
+    pub positions: Vec<PositionResponse>,
+}
+
+/// Config changes that have to wait for the timelock period
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockedChange {
+    Oracle {
+        oracle: String,
+    },
+    CollateralOracle {
+        collateral_oracle: String,
+    },
+    ProtocolFeeRate {
+        protocol_fee_rate: Decimal,
+    },
+    /// increasing the ratio does not need the timelock, see `UpdateAsset`
+    MinCollateralRatio {
+        asset_token: String,
+        min_collateral_ratio: Decimal,
+    },
+    TimelockPeriod {
+        timelock_period: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwnerResponse {
+    pub pending_owner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueuedChangeResponse {
+    pub id: u64,
+    pub change: TimelockedChange,
+    pub executable_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueuedChangesResponse {
+    pub changes: Vec<QueuedChangeResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorApprovalResponse {
+    pub operator: String,
+    pub position_idx: Option<Uint128>,
+    pub operations: Option<Vec<Operation>>,
+    pub expires: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorApprovalsResponse {
+    pub owner: String,
+    pub approvals: Vec<OperatorApprovalResponse>,
+}
+
+/// Position operations that can be paused
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Open,
+    Deposit,
+    Mint,
+    Withdraw,
+    Burn,
+    Auction,
+    FlashMint,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Open => write!(f, "open"),
+            Operation::Deposit => write!(f, "deposit"),
+            Operation::Mint => write!(f, "mint"),
+            Operation::Withdraw => write!(f, "withdraw"),
+            Operation::Burn => write!(f, "burn"),
+            Operation::Auction => write!(f, "auction"),
+            Operation::FlashMint => write!(f, "flash_mint"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseInfo {
+    /// every operation is paused
+    pub all: bool,
+    pub operations: Vec<Operation>,
+}
+
+impl PauseInfo {
+    pub fn is_paused(&self, operation: Operation) -> bool {
+        self.all || self.operations.contains(&operation)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseStatusResponse {
+    pub global: PauseInfo,
+    pub asset: Option<PauseInfo>,
+}
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+pub const SECONDS_PER_WEEK: u64 = 7 * SECONDS_PER_DAY;
+
+/// Weekly window the market of an asset is open in, as seconds from Monday 00:00 UTC;
+/// a window closing before it opens spans the turn of the week
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarketWindow {
+    pub open: u64,
+    pub close: u64,
+}
+
+/// Trading schedule of an asset; opening positions, mint, withdraw, collateral
+/// swaps, deleverage, self liquidation and auction are rejected outside of its
+/// windows and on its holidays
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarketHours {
+    pub windows: Vec<MarketWindow>,
+    /// days the market stays closed, as the unix time of their 00:00 UTC
+    #[serde(default)]
+    pub holidays: Vec<u64>,
+}
+
+impl MarketHours {
+    pub fn is_open(&self, time: u64) -> bool {
+        if self.holidays.contains(&(time - time % SECONDS_PER_DAY)) {
+            return false;
+        }
+
+        // the unix epoch fell on a thursday
+        let time_of_week: u64 = (time + 3 * SECONDS_PER_DAY) % SECONDS_PER_WEEK;
+        self.windows.iter().any(|window| {
+            if window.open <= window.close {
+                window.open <= time_of_week && time_of_week < window.close
+            } else {
+                window.open <= time_of_week || time_of_week < window.close
+            }
+        })
+    }
+}
+
+/// Fee rates charged per operation on an asset, taken in collateral
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct FeeSchedule {
+    /// charged on the minted value when opening a position
+    pub open_fee_rate: Decimal,
+    /// charged on the minted value when minting more against a position
+    pub mint_fee_rate: Decimal,
+    /// charged on the burned value
+    pub burn_fee_rate: Decimal,
+    /// charged on the liquidated value in auctions
+    pub liquidation_fee_rate: Decimal,
+    /// charged on the flash minted amount, paid in the asset
+    #[serde(default)]
+    pub flash_mint_fee_rate: Decimal,
+    /// charged on the repaid value of a self liquidation in place of the burn fee;
+    /// can not exceed the liquidation fee
+    #[serde(default)]
+    pub self_liquidation_fee_rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeScheduleResponse {
+    pub asset_token: String,
+    pub fee_schedule: FeeSchedule,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DebtCeilingResponse {
+    pub asset_token: String,
+    pub debt_ceiling: Option<Uint128>,
+    pub total_minted: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositCapResponse {
+    pub collateral: AssetInfo,
+    pub max_deposit: Option<Uint128>,
+    pub total_deposited: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AssetStatsResponse {
+    pub asset_token: String,
+    pub total_minted: Uint128,
+    pub position_count: u64,
+    /// total minted debt valued in base denom; zero if the asset can not be priced
+    pub total_minted_value: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollateralStatsResponse {
+    pub collateral: AssetInfo,
+    pub total_deposited: Uint128,
+    /// total deposited collateral valued in base denom; zero if it can not be priced
+    pub total_deposited_value: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProtocolStatsResponse {
+    pub assets: Vec<AssetStatsResponse>,
+    pub collaterals: Vec<CollateralStatsResponse>,
+    /// sum of all minted debt in base denom
+    pub total_debt_value: Uint128,
+    /// sum of all locked collateral in base denom
+    pub total_collateral_value: Uint128,
 }
@@ -0,0 +1,27 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::Decimal;
+
+/// Subset of the oracle interface used by the protocol
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Price of the asset in its quote denom; fails if older than `timeframe` seconds
+    Price {
+        asset_token: String,
+        timeframe: Option<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceResponse {
+    pub rate: Decimal,
+    pub last_updated: u64,
+    /// denom the rate is quoted in; base denom if not set
+    #[serde(default)]
+    pub quote_denom: Option<String>,
+    /// half width of the confidence band around `rate`; zero if the source publishes none
+    #[serde(default)]
+    pub confidence: Decimal,
+}
@@ -0,0 +1,232 @@
+use crate::asset::{Asset, AssetInfo};
+use crate::mint::{MarketHours, MarketWindow, SECONDS_PER_DAY};
+use cosmwasm_std::testing::{mock_info, MockApi, MockQuerier};
+use cosmwasm_std::{
+    coin, to_json_binary, Addr, BankMsg, CosmosMsg, Decimal, Empty, QuerierWrapper, StdError,
+    Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+
+fn token(amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        amount: Uint128::from(amount),
+    }
+}
+
+fn native(amount: u128) -> Asset {
+    Asset {
+        info: AssetInfo::NativeToken {
+            denom: "usei".to_string(),
+        },
+        amount: Uint128::from(amount),
+    }
+}
+
+#[test]
+fn into_msg_transfers_asset() {
+    let recipient = Addr::unchecked("addr0000");
+
+    let msg: CosmosMsg = token(123u128).into_msg(recipient.clone()).unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0000".to_string(),
+                amount: Uint128::from(123u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    // native tokens are sent untaxed
+    let msg: CosmosMsg = native(123u128).into_msg(recipient).unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![coin(123u128, "usei")],
+        })
+    );
+}
+
+#[test]
+fn into_msg_with_tax_deducts_tax() {
+    let recipient = Addr::unchecked("addr0000");
+
+    let msg: CosmosMsg = native(1_000_000u128)
+        .into_msg_with_tax(
+            recipient.clone(),
+            Decimal::percent(1),
+            Uint128::from(1_000_000u128),
+        )
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![coin(990_099u128, "usei")],
+        })
+    );
+
+    // capped
+    let msg: CosmosMsg = native(1_000_000u128)
+        .into_msg_with_tax(
+            recipient.clone(),
+            Decimal::percent(1),
+            Uint128::from(1_000u128),
+        )
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![coin(999_000u128, "usei")],
+        })
+    );
+
+    // tokens are not taxed
+    assert_eq!(
+        token(1_000_000u128)
+            .compute_tax(Decimal::percent(1), Uint128::from(1_000u128))
+            .unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        token(1_000_000u128)
+            .deduct_tax(Decimal::percent(1), Uint128::from(1_000u128))
+            .unwrap_err(),
+        StdError::generic_err("cannot deduct tax from token asset")
+    );
+}
+
+#[test]
+fn into_transfer_from_msg_only_for_tokens() {
+    let owner = Addr::unchecked("owner0000");
+    let recipient = Addr::unchecked("addr0000");
+
+    let msg: CosmosMsg = token(123u128)
+        .into_transfer_from_msg(owner.clone(), recipient.clone())
+        .unwrap();
+    assert_eq!(
+        msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: "owner0000".to_string(),
+                recipient: "addr0000".to_string(),
+                amount: Uint128::from(123u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    native(123u128)
+        .into_transfer_from_msg::<Empty>(owner, recipient)
+        .unwrap_err();
+}
+
+#[test]
+fn checked_math_requires_same_asset() {
+    assert_eq!(
+        token(100u128).checked_add(&token(23u128)).unwrap(),
+        token(123u128)
+    );
+    assert_eq!(
+        token(123u128).checked_sub(&token(23u128)).unwrap(),
+        token(100u128)
+    );
+    token(23u128).checked_sub(&token(100u128)).unwrap_err();
+    assert_eq!(
+        token(100u128).checked_add(&native(23u128)).unwrap_err(),
+        StdError::generic_err("Asset info mismatch: asset0000 != usei")
+    );
+}
+
+#[test]
+fn sent_native_token_balance_asserted() {
+    native(100u128)
+        .assert_sent_native_token_balance(&mock_info("addr0000", &[coin(100u128, "usei")]))
+        .unwrap();
+    native(100u128)
+        .assert_sent_native_token_balance(&mock_info("addr0000", &[coin(99u128, "usei")]))
+        .unwrap_err();
+    native(100u128)
+        .assert_sent_native_token_balance(&mock_info("addr0000", &[]))
+        .unwrap_err();
+    native(0u128)
+        .assert_sent_native_token_balance(&mock_info("addr0000", &[]))
+        .unwrap();
+}
+
+#[test]
+fn native_decimals_have_to_be_configured() {
+    let querier: MockQuerier<Empty> = MockQuerier::new(&[]);
+    let querier = QuerierWrapper::<Empty>::new(&querier);
+    let api = MockApi::default();
+
+    let info = native(0u128).info;
+    assert_eq!(info.query_decimals(&querier, &api, Some(8u8)).unwrap(), 8u8);
+    assert_eq!(
+        info.query_decimals(&querier, &api, None).unwrap_err(),
+        StdError::generic_err("decimals must be configured for usei")
+    );
+}
+
+// 2019-10-23 02:23:39 UTC, a wednesday
+const WEDNESDAY: u64 = 1_571_797_419u64;
+
+#[test]
+fn market_open_within_weekly_windows() {
+    // monday to friday 14:30 - 21:00 UTC
+    let market_hours = MarketHours {
+        windows: (0..5u64)
+            .map(|day| MarketWindow {
+                open: day * SECONDS_PER_DAY + 52200u64,
+                close: day * SECONDS_PER_DAY + 75600u64,
+            })
+            .collect(),
+        holidays: vec![],
+    };
+    let midnight: u64 = WEDNESDAY - WEDNESDAY % SECONDS_PER_DAY;
+
+    assert!(!market_hours.is_open(WEDNESDAY));
+    assert!(market_hours.is_open(midnight + 52200u64));
+    assert!(!market_hours.is_open(midnight + 75600u64));
+    assert!(!market_hours.is_open(midnight + 3 * SECONDS_PER_DAY + 60000u64));
+
+    // a window closing before it opens spans the turn of the week
+    let market_hours = MarketHours {
+        windows: vec![MarketWindow {
+            open: 6 * SECONDS_PER_DAY,
+            close: SECONDS_PER_DAY,
+        }],
+        holidays: vec![],
+    };
+    assert!(!market_hours.is_open(WEDNESDAY));
+    assert!(market_hours.is_open(midnight + 4 * SECONDS_PER_DAY));
+    assert!(market_hours.is_open(midnight + 5 * SECONDS_PER_DAY));
+    assert!(!market_hours.is_open(midnight + 6 * SECONDS_PER_DAY));
+}
+
+#[test]
+fn market_closed_on_holidays() {
+    let midnight: u64 = WEDNESDAY - WEDNESDAY % SECONDS_PER_DAY;
+    let market_hours = MarketHours {
+        windows: vec![MarketWindow {
+            open: 0u64,
+            close: 5 * SECONDS_PER_DAY,
+        }],
+        holidays: vec![midnight],
+    };
+
+    assert!(market_hours.is_open(midnight - 1u64));
+    assert!(!market_hours.is_open(midnight));
+    assert!(!market_hours.is_open(WEDNESDAY));
+    assert!(market_hours.is_open(midnight + SECONDS_PER_DAY));
+}